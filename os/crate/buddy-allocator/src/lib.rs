@@ -1,47 +1,117 @@
 
-#![feature(alloc)]
-#![no_std]
-#![feature(lang_items)]
+#![cfg_attr(not(test), no_std)]
 
 extern crate alloc;
 use alloc::vec::Vec;
-use core::alloc::Layout;
+use core::cmp::max;
+use core::fmt;
 
 pub struct BuddyAllocator {
-    nodes : Vec<i8>,
+    nodes : Vec<i8>,    // 每个节点记录其子树中最大空闲块大小的对数，-1 表示没有空闲块
     level : u8,
+    free : usize,       // 空闲的页数
+    orders : Vec<i8>,   // 以每一页开始的已分配块大小的对数，-1 表示没有以该页开始的已分配块
+}
+
+// 释放时发现的错误，此时分配器的状态不会被修改
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeallocError {
+    OutOfRange,         // 页号超出了分配器管理的范围
+    NotAllocated,       // 没有以该页开始的已分配块，可能是重复释放
+    WrongSize(usize),   // 释放的页数与分配时不符，附带分配时实际分配的页数
+}
+
+impl fmt::Display for DeallocError {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DeallocError::OutOfRange => write!(f, "page out of the allocator"),
+            DeallocError::NotAllocated => write!(f, "block is not allocated (double free?)"),
+            DeallocError::WrongSize(size) => write!(f, "wrong size, the block has {} pages", size),
+        }
+    }
 }
 
 impl BuddyAllocator {
     pub fn new() -> Self {
-        let ret = BuddyAllocator{
+        BuddyAllocator{
             nodes : Vec::new(),
             level : 0,
-        };
-        ret
-    }
-
-    pub fn init(&mut self, level : u8) {
-        self.level = level;
-        let mut idx = 0;
-        for s in 0..level {
-            for _t in 0..(1 << s) {
-                self.nodes.push(0_i8);
-                self.nodes[idx] = ( level - s - 1 ) as i8;
-                idx = idx + 1;
+            free : 0,
+            orders : Vec::new(),
+        }
+    }
+
+    // 管理编号为 [0, pages) 的页，初始时所有页都不可用，需要通过 add_range 加入
+    pub fn init(&mut self, pages : usize) {
+        self.level = log2_up(pages) as u8 + 1;
+        self.free = 0;
+        self.nodes.clear();
+        self.nodes.resize((1 << self.level) - 1, -1);
+        self.orders.clear();
+        self.orders.resize(1 << (self.level - 1), -1);
+    }
+
+    // 将 [start, start + len) 中的页加入分配器，这段区间可以是任意的
+    pub fn add_range(&mut self, start : usize, len : usize) {
+        assert!(start + len <= 1 << (self.level - 1), "range out of the allocator");
+        let mut start = start;
+        let end = start + len;
+        while start < end {
+            // 以 start 对齐、且不超过剩余长度的最大的块
+            let mut size = 1;
+            while start % (size << 1) == 0 && start + (size << 1) <= end {
+                size <<= 1;
             }
+            self.free_block(start, log2_down(size) as i8);
+            start += size;
         }
     }
 
+    // 将 [start, start + len) 中的页标记为保留，这些页不会再被分配
+    pub fn reserve(&mut self, start : usize, len : usize) {
+        if len > 0 && !self.nodes.is_empty() {
+            self.reserve_node(0, 0, 1 << (self.level - 1), start, start + len);
+        }
+    }
+
+    // location 节点管理的页为 [node_start, node_start + node_size)
+    fn reserve_node(&mut self, location : usize, node_start : usize, node_size : usize, start : usize, end : usize) {
+        if end <= node_start || node_start + node_size <= start || self.nodes[location] < 0 {
+            return;
+        }
+        let order = log2_down(node_size) as i8;
+        if start <= node_start && node_start + node_size <= end && self.nodes[location] == order {
+            self.nodes[location] = -1;
+            self.free -= node_size;
+            return;
+        }
+        // 保留的区间只覆盖了节点的一部分，或节点中只有一部分空闲
+        self.split(location, order);
+        let half = node_size >> 1;
+        self.reserve_node((location << 1) + 1, node_start, half, start, end);
+        self.reserve_node((location + 1) << 1, node_start + half, half, start, end);
+        self.nodes[location] = max(self.nodes[(location << 1) + 1], self.nodes[(location + 1) << 1]);
+    }
+
+    // 一个完整的空闲块被拆开使用前，先将其子节点设为两个空闲的半块
+    fn split(&mut self, location : usize, order : i8) {
+        if order > 0 && self.nodes[location] == order {
+            self.nodes[(location << 1) + 1] = order - 1;
+            self.nodes[(location + 1) << 1] = order - 1;
+        }
+    }
+
+    // 分配 alloc_size 向上取整到 2 的幂的页数，没有足够大的空闲块时返回 None
     pub fn alloc(&mut self, alloc_size : usize) -> Option<usize> {
         let size = log2_up(alloc_size) as i8;
+        if self.nodes.is_empty() || size > self.nodes[0] {
+            return None;
+        }
         let mut location = 0;
         let mut height = self.level - 1;
         let ret;
         while height != 0 {
-            if size > self.nodes[location] {
-                panic!("memory is not enough ");
-            }
+            self.split(location, height as i8);
             if self.nodes[(location << 1) + 1] >= size {
                 location = (location << 1) + 1;
             }else if self.nodes[(location + 1) << 1] >= size{
@@ -49,10 +119,12 @@ impl BuddyAllocator {
             }else{
                 break;
             }
-            height = height - 1;
+            height -= 1;
         }
         ret = (location + 1) * (1 << height) - (1 << (self.level - 1));
         self.nodes[location] = -1;
+        self.free -= 1 << size;
+        self.orders[ret] = size;
         while location > 0 {    // 回溯，更新父辈节点
             if location & 0x1 > 0 { // 当前节点的下标为奇数
                 if self.nodes[location] > self.nodes[location + 1] {    // 当前节点的值大于兄弟节点的值
@@ -60,7 +132,7 @@ impl BuddyAllocator {
                 }else{ // 兄弟节点的值不小于当前节点的值
                     self.nodes[location >> 1] = self.nodes[location + 1];
                 }
-                location = location >> 1;
+                location >>= 1;
             }else{  //当前节点的下标为偶数
                 if self.nodes[location] > self.nodes[location - 1] {
                     self.nodes[(location - 1) >> 1] = self.nodes[location];
@@ -73,8 +145,22 @@ impl BuddyAllocator {
         Some(ret)
     }
 
-    pub fn dealloc(&mut self, address : usize, dealloc_size : usize){
-        let size = log2_down(dealloc_size) as i8;
+    // 释放以 address 开始、由 alloc(dealloc_size) 分配的块
+    pub fn dealloc(&mut self, address : usize, dealloc_size : usize) -> Result<(), DeallocError> {
+        let order = *self.orders.get(address).ok_or(DeallocError::OutOfRange)?;
+        if order < 0 {
+            return Err(DeallocError::NotAllocated);
+        }
+        if dealloc_size == 0 || log2_up(dealloc_size) as i8 != order {
+            return Err(DeallocError::WrongSize(1 << order));
+        }
+        self.orders[address] = -1;
+        self.free_block(address, order);
+        Ok(())
+    }
+
+    // 将以 address 开始、大小为 2^size 页的块标记为空闲，并与空闲的伙伴合并
+    fn free_block(&mut self, address : usize, size : i8) {
         let mut location = address + (1 << (self.level - 1)) - 1;
         let mut height = 0_i8;
         while size > height {
@@ -86,6 +172,7 @@ impl BuddyAllocator {
             };
         }
         self.nodes[location] = size as i8;
+        self.free += 1 << size;
         while location > 0 {
             if location & 0x1 > 0 { //　奇数下标
                 if self.nodes[location] == self.nodes[location + 1] && self.nodes[location] == height{
@@ -93,7 +180,7 @@ impl BuddyAllocator {
                 }else if self.nodes[location] > self.nodes[location >> 1] {
                     self.nodes[location >> 1] = self.nodes[location];
                 }
-                location = location >> 1;
+                location >>= 1;
             }else{ // 偶数下标
                 if self.nodes[location] == self.nodes[location - 1] && self.nodes[location] == height{
                     self.nodes[(location - 1) >> 1] = self.nodes[location] + 1;
@@ -102,11 +189,30 @@ impl BuddyAllocator {
                 }
                 location = (location - 1) >> 1;
             }
-            height = height + 1;
+            height += 1;
+        }
+    }
+
+    // 空闲的页数
+    pub fn free_pages(&self) -> usize {
+        self.free
+    }
+
+    // 能够一次分配的最大页数
+    pub fn largest_free_block(&self) -> usize {
+        match self.nodes.first() {
+            Some(&size) if size >= 0 => 1 << size,
+            _ => 0,
         }
     }
 }
 
+impl Default for BuddyAllocator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 
 #[inline(always)]
 fn log2_up(x: usize) -> usize {    // 以２为底的对数向上取整的值，主要考虑分配内存时应该向上取整
@@ -118,7 +224,7 @@ fn log2_up(x: usize) -> usize {    // 以２为底的对数向上取整的值，
         temp_x >>= 1;
     }
     if x - (1 << pos) != 0 {
-        pos = pos + 1;
+        pos += 1;
     }
     pos as usize
 }
@@ -135,7 +241,5 @@ pub fn log2_down(x: usize) -> usize {    // 以２为底的对数向下取整的
     pos as usize
 }
 
-#[lang = "oom"]
-fn oom(_: Layout) -> ! {
-    panic!("out of memory");
-} 
\ No newline at end of file
+#[cfg(test)]
+mod tests; 
\ No newline at end of file
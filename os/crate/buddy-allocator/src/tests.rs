@@ -0,0 +1,179 @@
+use super::*;
+
+// 简单的伪随机数生成器 (xorshift) ，保证测试可以复现
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> usize {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0 as usize
+    }
+
+    fn below(&mut self, n : usize) -> usize {
+        self.next() % n
+    }
+}
+
+fn allocator(pages : usize) -> BuddyAllocator {
+    let mut allocator = BuddyAllocator::new();
+    allocator.init(pages);
+    allocator.add_range(0, pages);
+    allocator
+}
+
+#[test]
+fn log2() {
+    assert_eq!(log2_up(1), 0);
+    assert_eq!(log2_up(2), 1);
+    assert_eq!(log2_up(3), 2);
+    assert_eq!(log2_up(4), 2);
+    assert_eq!(log2_up(5), 3);
+    assert_eq!(log2_up(1 << 20), 20);
+    assert_eq!(log2_up((1 << 20) + 1), 21);
+    assert_eq!(log2_down(1), 0);
+    assert_eq!(log2_down(2), 1);
+    assert_eq!(log2_down(3), 1);
+    assert_eq!(log2_down(4), 2);
+    assert_eq!(log2_down(7), 2);
+    assert_eq!(log2_down((1 << 20) - 1), 19);
+}
+
+#[test]
+fn alloc_aligned() {
+    let mut allocator = allocator(64);
+    for &size in [1, 2, 3, 4, 8, 5, 16].iter() {
+        let start = allocator.alloc(size).unwrap();
+        assert_eq!(start % (1 << log2_up(size)), 0);
+    }
+}
+
+#[test]
+fn alloc_until_exhausted() {
+    let mut allocator = allocator(16);
+    for i in 0..16 {
+        assert_eq!(allocator.free_pages(), 16 - i);
+        assert!(allocator.alloc(1).is_some());
+    }
+    assert_eq!(allocator.free_pages(), 0);
+    assert_eq!(allocator.largest_free_block(), 0);
+    assert_eq!(allocator.alloc(1), None);
+}
+
+#[test]
+fn dealloc_coalesce() {
+    let mut allocator = allocator(16);
+    let blocks : Vec<usize> = (0..16).map(|_| allocator.alloc(1).unwrap()).collect();
+    // 只释放偶数页时伙伴都不空闲，无法合并
+    for &start in blocks.iter().filter(|&&start| start % 2 == 0) {
+        allocator.dealloc(start, 1).unwrap();
+    }
+    assert_eq!(allocator.free_pages(), 8);
+    assert_eq!(allocator.largest_free_block(), 1);
+    assert_eq!(allocator.alloc(2), None);
+    // 全部释放后应当合并为一整块
+    for &start in blocks.iter().filter(|&&start| start % 2 == 1) {
+        allocator.dealloc(start, 1).unwrap();
+    }
+    assert_eq!(allocator.free_pages(), 16);
+    assert_eq!(allocator.largest_free_block(), 16);
+    assert_eq!(allocator.alloc(16), Some(0));
+}
+
+#[test]
+fn ranges_and_reserve() {
+    let mut allocator = BuddyAllocator::new();
+    allocator.init(100);
+    assert_eq!(allocator.free_pages(), 0);
+    assert_eq!(allocator.alloc(1), None);
+    allocator.add_range(3, 20);
+    allocator.add_range(50, 50);
+    assert_eq!(allocator.free_pages(), 70);
+    allocator.reserve(60, 5);
+    assert_eq!(allocator.free_pages(), 65);
+    let mut pages = Vec::new();
+    while let Some(start) = allocator.alloc(1) {
+        pages.push(start);
+    }
+    pages.sort();
+    let expected : Vec<usize> = (3..23).chain(50..60).chain(65..100).collect();
+    assert_eq!(pages, expected);
+}
+
+#[test]
+fn bad_dealloc() {
+    let mut allocator = allocator(16);
+    let start = allocator.alloc(4).unwrap();
+    assert_eq!(allocator.dealloc(start + 1, 1), Err(DeallocError::NotAllocated));
+    assert_eq!(allocator.dealloc(start, 8), Err(DeallocError::WrongSize(4)));
+    assert_eq!(allocator.dealloc(16, 1), Err(DeallocError::OutOfRange));
+    assert_eq!(allocator.dealloc(start, 3), Ok(()));
+    assert_eq!(allocator.dealloc(start, 4), Err(DeallocError::NotAllocated));
+    assert_eq!(allocator.free_pages(), 16);
+}
+
+// 随机地加入区间、保留区间、分配与释放，并与逐页记录状态的参考模型比较：
+// 分配的块必须对齐、位于可用区间内且互不重叠，空闲页数必须与模型一致，全部释放后不能有泄漏
+#[test]
+fn random_against_model() {
+    let mut rng = Rng(0x2019_0902);
+    for _ in 0..200 {
+        let pages = rng.below(300) + 1;
+        let mut allocator = BuddyAllocator::new();
+        allocator.init(pages);
+        let mut available = vec![false; pages];
+        for _ in 0..3 {
+            let start = rng.below(pages);
+            let len = rng.below(pages - start) + 1;
+            if available[start..start + len].iter().all(|&a| !a) {
+                allocator.add_range(start, len);
+                for a in available[start..start + len].iter_mut() {
+                    *a = true;
+                }
+            }
+        }
+        let start = rng.below(pages);
+        let len = rng.below(pages - start) + 1;
+        allocator.reserve(start, len);
+        for a in available[start..start + len].iter_mut() {
+            *a = false;
+        }
+        let total = available.iter().filter(|&&a| a).count();
+        assert_eq!(allocator.free_pages(), total);
+
+        let mut used = vec![false; pages];
+        let mut blocks : Vec<(usize, usize)> = Vec::new();
+        for _ in 0..500 {
+            if rng.below(3) != 0 {
+                let size = rng.below(8) + 1;
+                let block_size = 1 << log2_up(size);
+                let largest = allocator.largest_free_block();
+                match allocator.alloc(size) {
+                    Some(start) => {
+                        assert!(block_size <= largest);
+                        assert_eq!(start % block_size, 0);
+                        for page in start..start + block_size {
+                            assert!(available[page] && !used[page]);
+                            used[page] = true;
+                        }
+                        blocks.push((start, size));
+                    }
+                    None => assert!(block_size > largest),
+                }
+            } else if !blocks.is_empty() {
+                let (start, size) = blocks.swap_remove(rng.below(blocks.len()));
+                allocator.dealloc(start, size).unwrap();
+                for page in &mut used[start..start + (1 << log2_up(size))] {
+                    *page = false;
+                }
+            }
+            let free = (0..pages).filter(|&page| available[page] && !used[page]).count();
+            assert_eq!(allocator.free_pages(), free);
+        }
+        for (start, size) in blocks {
+            allocator.dealloc(start, size).unwrap();
+        }
+        assert_eq!(allocator.free_pages(), total);
+    }
+}
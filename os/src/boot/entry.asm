@@ -27,6 +27,8 @@ remove_identity_map:
     lui sp, %hi(bootstacktop)
 
     # 3. call rust_main
+    # a0 = hartid, a1 = 设备树的物理地址，由 OpenSBI 传入，以上代码没有修改它们
+    # 它们作为 rust_main 的两个参数，用于构造 BootInfo
     call    rust_main
 
     .section .bss.stack
@@ -43,8 +45,14 @@ boot_page_table_sv32:
     .zero 4 * 513
     # 0x80400000 -> 0x80400000 (4M)
     .word (0x80400 << 10) | 0xcf # VRWXAD
-    .zero 4 * 255
-    # 0xC0400000 -> 0x80400000 (4M)
-    .word (0x80400 << 10) | 0xcf # VRWXAD
     .zero 4 * 254
+    # 0xC0000000 -> 0x80000000 (1012M)
+    # 内核可以通过 va = pa + KERNEL_OFFSET - MEMORY_OFFSET 访问全部物理内存（包括设备树）
+    # 一直映射到递归页表项之前，实际的物理内存大小在启动后从设备树中获得
+    .set ppn, 0x80000
+    .rept 253
+    .word (ppn << 10) | 0xcf # VRWXAD
+    .set ppn, ppn + 0x400
+    .endr
+    .zero 4 * 3
 boot_page_table_sv32_top:
@@ -1,5 +1,16 @@
 pub const KERNEL_HEAP_SIZE: usize = 0x0010_0000;
+pub const SWAP_SIZE: usize = 0x0010_0000;
 pub const MEMORY_OFFSET: usize = 0x8000_0000;
 pub const KERNEL_OFFSET: usize = 0xC000_0000;
-pub const MEMORY_END: usize = 0x8800_0000;
 pub const PAGE_SIZE: usize = 4096;
+pub const MAX_THREAD_NUM: usize = 64;
+pub const TIME_SLICE: usize = 5;
+pub const PHYSICAL_MEMORY_OFFSET: usize = KERNEL_OFFSET - MEMORY_OFFSET;
+pub const RECURSIVE_INDEX: usize = 0x3fd;
+// 映射设备 MMIO 的内核虚拟地址区间，紧挨在递归页表之前
+pub const MMIO_SIZE: usize = 0x0200_0000;
+pub const MMIO_OFFSET: usize = (RECURSIVE_INDEX << 22) - MMIO_SIZE;
+// 线性映射能够覆盖的物理内存上界，之后的虚拟地址用于 MMIO 与递归页表
+pub const MEMORY_END_LIMIT: usize = MMIO_OFFSET - PHYSICAL_MEMORY_OFFSET;
+pub const USER_STACK_SIZE: usize = 0x4000;
+pub const USER_STACK_OFFSET: usize = 0x8000_0000 - USER_STACK_SIZE;
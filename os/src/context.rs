@@ -4,6 +4,7 @@ use riscv::register::sstatus;
 use riscv::register::{scause::Scause, sstatus::Sstatus};
 
 #[repr(C)]
+#[derive(Clone)]
 pub struct TrapFrame {
     pub x: [usize; 32],   // General registers
     pub sstatus: Sstatus, // Supervisor Status Register
@@ -16,6 +17,18 @@ impl TrapFrame {
     pub fn increase_sepc(self: &mut Self) {
         self.sepc = self.sepc + 4;
     }
+
+    // 用户程序开始运行时的中断帧，sret 之后从 entry 开始在 U 态运行
+    pub fn new_user_thread(entry: usize, ustack_top: usize) -> TrapFrame {
+        let mut tf: TrapFrame = unsafe { zeroed() };
+        tf.x[2] = ustack_top;
+        tf.sepc = entry;
+        tf.sstatus = sstatus::read();
+        tf.sstatus.set_spp(sstatus::SPP::User); // sret 之后进入 U 态，此后每次中断都会切换到内核栈
+        tf.sstatus.set_spie(true);
+        tf.sstatus.set_sie(false);
+        tf
+    }
 }
 
 #[repr(C)]
@@ -29,7 +42,7 @@ impl Context {
     }
 
     pub unsafe fn new_kernel_thread(
-        entry: extern "C" fn(usize) -> !,
+        entry: extern "C" fn(usize) -> usize,
         arg: usize,
         kstack_top: usize,
         satp: usize,
@@ -37,6 +50,20 @@ impl Context {
         ContextContent::new_kernel_thread(entry, arg, kstack_top, satp).push_at(kstack_top)
     }
 
+    pub unsafe fn new_user_thread(
+        entry: usize,
+        ustack_top: usize,
+        kstack_top: usize,
+        satp: usize,
+    ) -> Context {
+        ContextContent::new_user_thread(entry, ustack_top, satp).push_at(kstack_top)
+    }
+
+    // fork 出的子线程：从 tf 处返回用户态，且 fork 的返回值为 0
+    pub unsafe fn new_fork(tf: &TrapFrame, kstack_top: usize, satp: usize) -> Context {
+        ContextContent::new_fork(tf, satp).push_at(kstack_top)
+    }
+
     #[naked]
     #[inline(never)]
     pub unsafe extern "C" fn switch(&mut self, target: &mut Context) {
@@ -49,22 +76,52 @@ struct ContextContent {
     ra: usize,      // 返回地址
     satp: usize,    //　二级页表所在位置
     s: [usize; 12], // 被调用者保存的寄存器
+    tf: TrapFrame,  // 新线程第一次被调度时，经由 __trapret 恢复的中断帧
+}
+
+extern "C" {
+    fn __trapret();
+    fn kernel_thread_exit(code: usize) -> !;
 }
 
 impl ContextContent {
     fn new_kernel_thread(
-        entry: extern "C" fn(usize) -> !,
+        entry: extern "C" fn(usize) -> usize,
         arg: usize,
         kstack_top: usize,
         satp: usize,
     ) -> ContextContent {
         let mut content: ContextContent = unsafe { zeroed() };
-        content.ra = entry as usize;
+        content.ra = __trapret as usize; // switch 返回后进入 __trapret ，由 sret 跳转到 entry
         content.satp = satp;
-        content.s[0] = arg;
-        let mut sstatus_ = sstatus::read();
-        sstatus_.set_spp(sstatus::SPP::Supervisor); // 代表 sret 之后的特权级仍为 Ｓ
-        content.s[1] = sstatus_.bits();
+        content.tf.x[1] = kernel_thread_exit as usize; // entry 返回后，以其返回值作为退出码退出
+        content.tf.x[2] = kstack_top;
+        content.tf.x[10] = arg;
+        content.tf.sepc = entry as usize;
+        content.tf.sstatus = sstatus::read();
+        content.tf.sstatus.set_spp(sstatus::SPP::Supervisor); // 代表 sret 之后的特权级仍为 Ｓ
+        content.tf.sstatus.set_spie(true); // sret 之后开启中断，使新线程可以被时钟中断抢占
+        content.tf.sstatus.set_sie(false);
+        content
+    }
+
+    fn new_user_thread(entry: usize, ustack_top: usize, satp: usize) -> ContextContent {
+        ContextContent {
+            ra: __trapret as usize,
+            satp,
+            s: [0; 12],
+            tf: TrapFrame::new_user_thread(entry, ustack_top),
+        }
+    }
+
+    fn new_fork(tf: &TrapFrame, satp: usize) -> ContextContent {
+        let mut content = ContextContent {
+            ra: __trapret as usize,
+            satp,
+            s: [0; 12],
+            tf: tf.clone(),
+        };
+        content.tf.x[10] = 0;
         content
     }
 
@@ -0,0 +1,100 @@
+// 设备驱动框架：遍历设备树，按节点的 compatible 属性为设备找到驱动并调用其 probe
+use crate::fdt::{Fdt, Node};
+use crate::init::BootInfo;
+use crate::memory::map_mmio;
+use alloc::vec::Vec;
+use lazy_static::*;
+use spin::Mutex;
+
+pub mod serial;
+pub mod test_device;
+
+// 设备树中描述的一个设备
+#[derive(Clone, Debug)]
+pub struct Device {
+    pub name: &'static str,        // 设备树中的节点名，如 uart@10000000
+    pub compatible: &'static str,  // 与驱动匹配上的 compatible 字符串
+    pub regs: Vec<(usize, usize)>, // MMIO 区间，(起始物理地址, 大小)
+    pub mmio: Vec<usize>,          // 每个 MMIO 区间映射到的内核虚拟地址
+    pub irqs: Vec<u32>,            // 中断号
+}
+
+pub struct Driver {
+    pub name: &'static str,
+    pub compatible: &'static [&'static str], // 驱动支持的 compatible 字符串
+    pub probe: fn(&Device) -> Result<(), &'static str>,
+}
+
+lazy_static! {
+    static ref DRIVERS: Mutex<Vec<&'static Driver>> = Mutex::new(Vec::new());
+}
+
+pub fn register(driver: &'static Driver) {
+    DRIVERS.lock().push(driver);
+}
+
+// 为节点找到驱动，节点的 compatible 中越靠前的越具体，优先匹配
+fn find_driver(node: &Node) -> Option<(&'static Driver, &'static str)> {
+    let drivers = DRIVERS.lock();
+    node.compatible().find_map(|compatible| {
+        drivers
+            .iter()
+            .find(|driver| driver.compatible.contains(&compatible))
+            .map(|&driver| (driver, compatible))
+    })
+}
+
+// 遍历设备树，probe 所有已注册驱动支持的设备，probe 之前先映射设备的 MMIO 区间
+// QEMU virt 中 /soc 的 ranges 为空，这里直接把 reg 当作物理地址，不做地址转换
+pub fn probe(fdt: &Fdt) {
+    fdt.root().walk(&mut |node| {
+        if node
+            .prop_str("status")
+            .map_or(false, |status| status != "okay")
+        {
+            return;
+        }
+        let (driver, compatible) = match find_driver(node) {
+            Some(found) => found,
+            None => return,
+        };
+        let regs: Vec<(usize, usize)> = node
+            .reg()
+            .map(|(start, size)| (start as usize, size as usize))
+            .collect();
+        let mmio = match regs
+            .iter()
+            .map(|&(start, size)| map_mmio(start, size))
+            .collect()
+        {
+            Some(mmio) => mmio,
+            None => {
+                println!("{}: map {} {:x?} failed", driver.name, node.name(), regs);
+                return;
+            }
+        };
+        let device = Device {
+            name: node.name(),
+            compatible,
+            regs,
+            mmio,
+            irqs: node.interrupts().collect(),
+        };
+        match (driver.probe)(&device) {
+            Ok(()) => println!(
+                "{}: {} {:x?} irq {:?}",
+                driver.name, device.name, device.regs, device.irqs
+            ),
+            Err(err) => println!("{}: probe {} failed: {}", driver.name, device.name, err),
+        }
+    });
+}
+
+pub fn init(boot_info: &BootInfo) {
+    let fdt = unsafe { Fdt::from_addr(crate::memory::access_pa_via_va(boot_info.dtb)) }
+        .expect("invalid device tree!");
+    register(&serial::DRIVER);
+    register(&test_device::DRIVER);
+    probe(&fdt);
+    println!("++++probe devices succeed!++++");
+}
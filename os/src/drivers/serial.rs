@@ -0,0 +1,38 @@
+// 串口（16550）：probe 之后控制台输出直接写串口，之前仍通过 SBI 完成
+use super::{Device, Driver};
+use core::ptr;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+pub static DRIVER: Driver = Driver {
+    name: "serial",
+    compatible: &["ns16550a"],
+    probe,
+};
+
+// 寄存器相对于 MMIO 起始地址的偏移
+const THR: usize = 0; // 发送保持寄存器
+const LSR: usize = 5; // 线路状态寄存器
+const LSR_THR_EMPTY: u8 = 1 << 5;
+
+// 第一个串口的寄存器的虚拟地址，为 0 表示还没有找到串口
+// 输出时不能加锁，否则在持有锁时 panic 会无法打印
+static BASE: AtomicUsize = AtomicUsize::new(0);
+
+fn probe(device: &Device) -> Result<(), &'static str> {
+    let base = *device.mmio.first().ok_or("no mmio region")?;
+    BASE.compare_and_swap(0, base, Ordering::Release);
+    Ok(())
+}
+
+// 通过串口输出一个字节，还没有找到串口时返回 false
+pub fn putchar(ch: u8) -> bool {
+    let base = BASE.load(Ordering::Acquire);
+    if base == 0 {
+        return false;
+    }
+    unsafe {
+        while ptr::read_volatile((base + LSR) as *const u8) & LSR_THR_EMPTY == 0 {}
+        ptr::write_volatile((base + THR) as *mut u8, ch);
+    }
+    true
+}
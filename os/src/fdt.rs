@@ -0,0 +1,340 @@
+// 扁平设备树 (Flattened Device Tree) 的解析
+// 设备树由 OpenSBI 放在物理内存中并将地址传给内核，这里只读地访问它，不需要分配内存
+
+use core::slice;
+use core::str;
+
+const FDT_MAGIC: u32 = 0xd00d_feed;
+const FDT_BEGIN_NODE: u32 = 1;
+const FDT_END_NODE: u32 = 2;
+const FDT_PROP: u32 = 3;
+const FDT_NOP: u32 = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FdtError {
+    BadMagic,  // 不是设备树
+    Truncated, // 头部中的偏移超出了设备树的大小
+}
+
+#[derive(Clone, Copy)]
+pub struct Fdt {
+    data: &'static [u8],
+    structs: usize,    // 结构块的偏移
+    strings: usize,    // 字符串块的偏移
+    mem_rsvmap: usize, // 内存保留块的偏移
+}
+
+impl Fdt {
+    // addr 为设备树在内核地址空间中的地址
+    pub unsafe fn from_addr(addr: usize) -> Result<Fdt, FdtError> {
+        let header = slice::from_raw_parts(addr as *const u8, 40);
+        if be32(header, 0) != FDT_MAGIC {
+            return Err(FdtError::BadMagic);
+        }
+        let data = slice::from_raw_parts(addr as *const u8, be32(header, 4) as usize);
+        let fdt = Fdt {
+            data,
+            structs: be32(header, 8) as usize,
+            strings: be32(header, 12) as usize,
+            mem_rsvmap: be32(header, 16) as usize,
+        };
+        if fdt.structs >= data.len() || fdt.strings > data.len() || fdt.mem_rsvmap >= data.len() {
+            return Err(FdtError::Truncated);
+        }
+        Ok(fdt)
+    }
+
+    pub fn total_size(&self) -> usize {
+        self.data.len()
+    }
+
+    // 根节点中 reg 的格式由规范规定的默认值给出
+    pub fn root(&self) -> Node {
+        self.node_at(self.skip_nop(self.structs), 2, 1)
+    }
+
+    // 按路径查找节点，路径中不带 @ 的部分可以匹配任意单元地址，如 /soc/uart 匹配 /soc/uart@10000000
+    pub fn find(&self, path: &str) -> Option<Node> {
+        let mut node = self.root();
+        for name in path.split('/').filter(|name| !name.is_empty()) {
+            node = node.children().find(|child| child.matches(name))?;
+        }
+        Some(node)
+    }
+
+    // 内存保留块 (/memreserve/) 中的区间，返回 (起始地址, 大小)
+    pub fn mem_reserves(&self) -> MemReserves {
+        MemReserves {
+            fdt: *self,
+            offset: self.mem_rsvmap,
+        }
+    }
+
+    fn skip_nop(&self, mut offset: usize) -> usize {
+        while be32(self.data, offset) == FDT_NOP {
+            offset += 4;
+        }
+        offset
+    }
+
+    fn string_at(&self, offset: usize) -> &'static str {
+        let data = &self.data[offset..];
+        let len = data
+            .iter()
+            .position(|&c| c == 0)
+            .unwrap_or_else(|| data.len());
+        str::from_utf8(&data[..len]).unwrap_or("")
+    }
+
+    // offset 处为 FDT_BEGIN_NODE
+    fn node_at(&self, offset: usize, address_cells: u32, size_cells: u32) -> Node {
+        let name = self.string_at(offset + 4);
+        Node {
+            fdt: *self,
+            begin: offset,
+            props: align4(offset + 4 + name.len() + 1),
+            name,
+            address_cells,
+            size_cells,
+        }
+    }
+
+    // offset 处为 FDT_PROP ，返回属性和下一个标记的偏移
+    fn prop_at(&self, offset: usize) -> (Property, usize) {
+        let len = be32(self.data, offset + 4) as usize;
+        let name = self.string_at(self.strings + be32(self.data, offset + 8) as usize);
+        let value = &self.data[offset + 12..offset + 12 + len];
+        (Property { name, value }, align4(offset + 12 + len))
+    }
+
+    // 返回 begin 处开始的节点（包括其所有子节点）之后的偏移
+    fn end_of(&self, begin: usize) -> usize {
+        let mut depth = 0;
+        let mut offset = begin;
+        loop {
+            match be32(self.data, offset) {
+                FDT_BEGIN_NODE => {
+                    depth += 1;
+                    offset = align4(offset + 4 + self.string_at(offset + 4).len() + 1);
+                }
+                FDT_END_NODE => {
+                    depth -= 1;
+                    offset += 4;
+                    if depth == 0 {
+                        return offset;
+                    }
+                }
+                FDT_PROP => offset = self.prop_at(offset).1,
+                FDT_NOP => offset += 4,
+                _ => return self.data.len(),
+            }
+        }
+    }
+}
+
+// 设备树中的一个节点
+#[derive(Clone, Copy)]
+pub struct Node {
+    fdt: Fdt,
+    begin: usize, // FDT_BEGIN_NODE 标记的偏移
+    props: usize, // 节点名之后第一个标记的偏移
+    name: &'static str,
+    address_cells: u32, // 父节点的 #address-cells ，决定 reg 中地址的长度
+    size_cells: u32,    // 父节点的 #size-cells
+}
+
+impl Node {
+    // 包括单元地址的完整节点名，如 memory@80000000
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    // 去掉单元地址的节点名
+    pub fn base_name(&self) -> &'static str {
+        self.name.split('@').next().unwrap()
+    }
+
+    fn matches(&self, name: &str) -> bool {
+        self.name == name || (!name.contains('@') && self.base_name() == name)
+    }
+
+    pub fn props(&self) -> Props {
+        Props {
+            fdt: self.fdt,
+            offset: self.props,
+        }
+    }
+
+    pub fn prop(&self, name: &str) -> Option<&'static [u8]> {
+        self.props()
+            .find(|prop| prop.name == name)
+            .map(|prop| prop.value)
+    }
+
+    pub fn prop_u32(&self, name: &str) -> Option<u32> {
+        self.prop(name)
+            .filter(|value| value.len() >= 4)
+            .map(|value| be32(value, 0))
+    }
+
+    pub fn prop_str(&self, name: &str) -> Option<&'static str> {
+        self.prop(name).map(|value| {
+            let len = value
+                .iter()
+                .position(|&c| c == 0)
+                .unwrap_or_else(|| value.len());
+            str::from_utf8(&value[..len]).unwrap_or("")
+        })
+    }
+
+    // compatible 属性中的各个字符串，越靠前的越具体
+    pub fn compatible(&self) -> impl Iterator<Item = &'static str> {
+        self.prop("compatible")
+            .unwrap_or(&[])
+            .split(|&c| c == 0)
+            .filter(|s| !s.is_empty())
+            .map(|s| str::from_utf8(s).unwrap_or(""))
+    }
+
+    // interrupts 属性中的中断号，假设中断控制器的 #interrupt-cells 为 1 （如 PLIC ）
+    pub fn interrupts(&self) -> impl Iterator<Item = u32> {
+        self.prop("interrupts")
+            .unwrap_or(&[])
+            .chunks_exact(4)
+            .map(|cell| be32(cell, 0))
+    }
+
+    // reg 属性中的各个区间，返回 (起始地址, 大小)
+    pub fn reg(&self) -> Reg {
+        Reg {
+            data: self.prop("reg").unwrap_or(&[]),
+            address_cells: self.address_cells as usize,
+            size_cells: self.size_cells as usize,
+        }
+    }
+
+    pub fn children(&self) -> Children {
+        Children {
+            fdt: self.fdt,
+            offset: self.props,
+            address_cells: self.prop_u32("#address-cells").unwrap_or(2),
+            size_cells: self.prop_u32("#size-cells").unwrap_or(1),
+        }
+    }
+
+    // 先序遍历以该节点为根的子树
+    pub fn walk(&self, f: &mut impl FnMut(&Node)) {
+        f(self);
+        for child in self.children() {
+            child.walk(f);
+        }
+    }
+}
+
+pub struct Property {
+    pub name: &'static str,
+    pub value: &'static [u8],
+}
+
+pub struct Props {
+    fdt: Fdt,
+    offset: usize,
+}
+
+impl Iterator for Props {
+    type Item = Property;
+
+    fn next(&mut self) -> Option<Property> {
+        self.offset = self.fdt.skip_nop(self.offset);
+        if be32(self.fdt.data, self.offset) != FDT_PROP {
+            return None;
+        }
+        let (prop, next) = self.fdt.prop_at(self.offset);
+        self.offset = next;
+        Some(prop)
+    }
+}
+
+pub struct Children {
+    fdt: Fdt,
+    offset: usize,
+    address_cells: u32,
+    size_cells: u32,
+}
+
+impl Iterator for Children {
+    type Item = Node;
+
+    fn next(&mut self) -> Option<Node> {
+        loop {
+            match be32(self.fdt.data, self.offset) {
+                FDT_PROP => self.offset = self.fdt.prop_at(self.offset).1,
+                FDT_NOP => self.offset += 4,
+                FDT_BEGIN_NODE => {
+                    let node = self
+                        .fdt
+                        .node_at(self.offset, self.address_cells, self.size_cells);
+                    self.offset = self.fdt.end_of(node.begin);
+                    return Some(node);
+                }
+                _ => return None,
+            }
+        }
+    }
+}
+
+pub struct Reg {
+    data: &'static [u8],
+    address_cells: usize,
+    size_cells: usize,
+}
+
+impl Iterator for Reg {
+    type Item = (u64, u64);
+
+    fn next(&mut self) -> Option<(u64, u64)> {
+        let len = (self.address_cells + self.size_cells) * 4;
+        if self.data.len() < len || len == 0 {
+            return None;
+        }
+        let address = read_cells(self.data, self.address_cells);
+        let size = read_cells(&self.data[self.address_cells * 4..], self.size_cells);
+        self.data = &self.data[len..];
+        Some((address, size))
+    }
+}
+
+pub struct MemReserves {
+    fdt: Fdt,
+    offset: usize,
+}
+
+impl Iterator for MemReserves {
+    type Item = (u64, u64);
+
+    // 保留块以一个大小为 0 的区间结束
+    fn next(&mut self) -> Option<(u64, u64)> {
+        let address = read_cells(&self.fdt.data[self.offset..], 2);
+        let size = read_cells(&self.fdt.data[self.offset + 8..], 2);
+        if size == 0 {
+            return None;
+        }
+        self.offset += 16;
+        Some((address, size))
+    }
+}
+
+// 设备树中的数据都是大端序的
+fn be32(data: &[u8], offset: usize) -> u32 {
+    let mut bytes = [0; 4];
+    bytes.copy_from_slice(&data[offset..offset + 4]);
+    u32::from_be_bytes(bytes)
+}
+
+fn read_cells(data: &[u8], cells: usize) -> u64 {
+    (0..cells).fold(0, |value, i| (value << 32) | u64::from(be32(data, i * 4)))
+}
+
+fn align4(offset: usize) -> usize {
+    (offset + 3) & !3
+}
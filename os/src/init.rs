@@ -1,10 +1,48 @@
+use crate::consts::*;
+use core::ops::Range;
+
 global_asm!(include_str!("boot/entry.asm"));
 
+// 启动信息：OpenSBI 通过 a0, a1 传入的 hartid 与设备树地址，以及链接脚本给出的内核镜像的位置
+#[derive(Clone, Debug)]
+pub struct BootInfo {
+    pub hartid: usize,
+    pub dtb: usize,                // 设备树的物理地址
+    pub kernel_virt: Range<usize>, // 内核镜像的虚拟地址范围
+    pub kernel_phys: Range<usize>, // 内核镜像的物理地址范围
+}
+
+impl BootInfo {
+    fn new(hartid: usize, dtb: usize) -> BootInfo {
+        let kernel_virt = start as usize..end as usize;
+        let kernel_phys =
+            kernel_virt.start - PHYSICAL_MEMORY_OFFSET..kernel_virt.end - PHYSICAL_MEMORY_OFFSET;
+        BootInfo {
+            hartid,
+            dtb,
+            kernel_virt,
+            kernel_phys,
+        }
+    }
+}
+
 #[no_mangle]
-pub fn rust_main() -> ! {
+pub extern "C" fn rust_main(hartid: usize, dtb: usize) -> ! {
+    let boot_info = BootInfo::new(hartid, dtb);
+    println!(
+        "hart {} boot, device tree at {:#x}, kernel at {:#x?}",
+        boot_info.hartid, boot_info.dtb, boot_info.kernel_phys
+    );
     crate::interrupt::init();
     crate::clock::init();
-    crate::memory::init();
+    crate::memory::init(&boot_info);
+    crate::drivers::init(&boot_info);
     crate::process::init();
-    loop {}
+    crate::process::run();
+}
+
+// Symbols provided by linker script
+extern "C" {
+    fn start();
+    fn end();
 }
@@ -1,6 +1,7 @@
 use crate::clock::{clock_set_next_event, TICK};
 use crate::context::TrapFrame;
 use riscv::register::scause::{Exception, Interrupt, Trap};
+use riscv::register::sstatus::SPP;
 use riscv::register::{sscratch, sstatus, stvec};
 
 global_asm!(include_str!("trap/trap.asm"));
@@ -20,24 +21,62 @@ pub fn init() {
 #[no_mangle]
 pub fn rust_trap(tf: &mut TrapFrame) {
     match tf.scause.cause() {
-        Trap::Exception(Exception::Breakpoint) => breakpoint(),
+        Trap::Exception(Exception::Breakpoint) => breakpoint(tf),
         Trap::Interrupt(Interrupt::SupervisorTimer) => super_timer(),
         Trap::Exception(Exception::InstructionPageFault) => page_fault(tf),
         Trap::Exception(Exception::LoadPageFault) => page_fault(tf),
         Trap::Exception(Exception::StorePageFault) => page_fault(tf),
-        _ => panic!("unexpected trap"),
+        Trap::Exception(Exception::UserEnvCall) => syscall(tf),
+        _ => unexpected(tf),
     }
 }
 
+// 来自 U 态的异常无法处理时，结束该进程而不是让内核崩溃
+fn kill_user(tf: &TrapFrame) {
+    if tf.sstatus.spp() == SPP::User {
+        crate::process::exit(-1isize as usize);
+    }
+}
+
+fn syscall(tf: &mut TrapFrame) {
+    // 返回用户态后应执行 ecall 的下一条指令
+    tf.increase_sepc();
+    let ret = crate::syscall::syscall(
+        tf.x[17],
+        [tf.x[10], tf.x[11], tf.x[12], tf.x[13], tf.x[14], tf.x[15]],
+        tf,
+    );
+    tf.x[10] = ret as usize;
+}
+
 fn page_fault(tf: &mut TrapFrame) {
+    let store = match tf.scause.cause() {
+        Trap::Exception(Exception::StorePageFault) => true,
+        _ => false,
+    };
+    if crate::process::handle_page_fault(tf.stval, store) {
+        return;
+    }
     println!("{:?} @ {:#x}", tf.scause.cause(), tf.stval);
+    kill_user(tf);
     panic!("page fault");
 }
 
-fn breakpoint() {
+fn breakpoint(tf: &TrapFrame) {
+    println!("breakpoint @ {:#x}", tf.sepc);
+    kill_user(tf);
     panic!("a breakpoint set by kernel");
 }
 
+// 非法指令、地址未对齐等异常
+fn unexpected(tf: &TrapFrame) {
+    println!("{:?} @ {:#x}", tf.scause.cause(), tf.stval);
+    if let Trap::Exception(_) = tf.scause.cause() {
+        kill_user(tf);
+    }
+    panic!("unexpected trap");
+}
+
 fn super_timer() {
     // 响应当前时钟中断的同时，手动设置下一个时钟中断
     clock_set_next_event();
@@ -47,4 +86,31 @@ fn super_timer() {
             println!("100 ticks!");
         }
     }
+    crate::process::tick();
+}
+
+// 关闭中断，并返回关闭前 sstatus.SIE 的状态
+#[inline(always)]
+pub fn disable_and_store() -> usize {
+    let sstatus: usize;
+    unsafe {
+        asm!("csrrci $0, sstatus, 1 << 1" : "=r"(sstatus) ::: "volatile");
+    }
+    sstatus & (1 << 1)
+}
+
+// 恢复 disable_and_store 之前的中断状态
+#[inline(always)]
+pub fn restore(flags: usize) {
+    unsafe {
+        asm!("csrs sstatus, $0" :: "r"(flags) :: "volatile");
+    }
+}
+
+// 开启中断并等待下一个中断到来
+#[inline(always)]
+pub fn enable_and_wfi() {
+    unsafe {
+        asm!("csrsi sstatus, 1 << 1; wfi" :::: "volatile");
+    }
 }
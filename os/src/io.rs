@@ -27,8 +27,11 @@ macro_rules! println {
     ($($arg:tt)*) => ($crate::print!("{}\n", format_args!($($arg)*)));
 }
 
+// 找到串口之后直接写串口，否则通过 SBI 输出
 pub fn putchar(ch: char) {
-    sbi::console_putchar(ch as u8 as usize);
+    if !crate::drivers::serial::putchar(ch as u8) {
+        sbi::console_putchar(ch as u8 as usize);
+    }
 }
 
 pub fn puts(s: &str) {
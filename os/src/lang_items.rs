@@ -1,8 +1,11 @@
+use core::alloc::Layout;
 use core::panic::PanicInfo;
 
 #[panic_handler]
 fn panic(info: &PanicInfo) -> ! {
     println!("{}", info);
+    // 在 QEMU 中运行时直接以失败结束
+    crate::drivers::test_device::exit(1);
     loop {}
 }
 
@@ -10,3 +13,8 @@ fn panic(info: &PanicInfo) -> ! {
 pub extern "C" fn abort() {
     panic!("abort!");
 }
+
+#[alloc_error_handler]
+fn oom(_: Layout) -> ! {
+    panic!("out of memory");
+}
@@ -5,19 +5,25 @@
 #![feature(global_asm)]
 #![feature(naked_functions)]
 
+extern crate alloc;
+
 #[macro_use]
 mod io;
 
 mod clock;
 mod consts;
 mod context;
+mod drivers;
+mod fdt;
 mod init;
 mod interrupt;
 mod lang_items;
 mod memory;
 mod process;
 mod sbi;
+mod sync;
+mod syscall;
 
-use buddy_system_allocator::LockedHeap;
+use memory::heap::KernelHeap;
 #[global_allocator]
-static HEAP_ALLOCATOR: LockedHeap = LockedHeap::empty();
+static HEAP_ALLOCATOR: KernelHeap = KernelHeap::new();
@@ -1,24 +1,67 @@
 use crate::consts::*;
-use buddy_allocator::{log2_down, BuddyAllocator};
+use crate::sync::IrqMutex;
+use alloc::vec;
+use alloc::vec::Vec;
+use buddy_allocator::BuddyAllocator;
+use core::sync::atomic::{AtomicBool, Ordering};
 use lazy_static::*;
 use riscv::addr::*;
-use spin::Mutex;
+use riscv::paging::{FrameAllocator, FrameDeallocator};
+
+mod tracker;
+
+pub use tracker::FrameTracker;
 
 // 物理页帧分配器
 lazy_static! {
-    pub static ref BUDDY_ALLOCATOR: Mutex<BuddyAllocator> = Mutex::new(BuddyAllocator::new());
+    pub static ref BUDDY_ALLOCATOR: IrqMutex<BuddyAllocator> = IrqMutex::new(BuddyAllocator::new());
 }
 
-pub fn init(start: usize, lenth: usize) {
-    BUDDY_ALLOCATOR
-        .lock()
-        .init(log2_down((start + lenth - MEMORY_OFFSET) / PAGE_SIZE) as u8);
-    alloc_frames((start - MEMORY_OFFSET - 1) / PAGE_SIZE + 1);
+// 物理页帧分配器是否已经建立，建立之前内核堆从启动堆中分配
+pub static FRAME_ALLOCATOR_READY: AtomicBool = AtomicBool::new(false);
+
+#[cfg(feature = "frame-poison")]
+const FRAME_POISON: u8 = 0xcc;
+
+// 映射在用户地址空间中的每个物理页帧被多少个页表项引用，以相对 MEMORY_OFFSET 的页号为下标
+// 计数不为 0 的页帧由页表项持有，最后一个页表项解除映射时被回收
+// 这张表在启动时一次分配好，物理内存不足时映射页帧也不需要再从堆中分配
+lazy_static! {
+    static ref MAPPED_FRAMES: IrqMutex<Vec<usize>> = IrqMutex::new(Vec::new());
+}
+
+// 管理物理地址 [MEMORY_OFFSET, end) 中的页帧，初始时都不可用
+pub fn init(end: usize) {
+    // 建立分配器时需要使用堆，而堆会向物理页帧分配器申请页，因此不能持有锁
+    let mut allocator = BuddyAllocator::new();
+    allocator.init((end - MEMORY_OFFSET) / PAGE_SIZE);
+    *BUDDY_ALLOCATOR.lock() = allocator;
+    FRAME_ALLOCATOR_READY.store(true, Ordering::Release);
     println!("++++init frame allocator succeed!++++");
 }
 
-pub fn alloc_frame() -> Option<Frame> {
-    alloc_frames(1)
+// 物理内存 [start, start + length) 可以被分配，只有完整包含在其中的页帧会被加入
+pub fn add_range(start: usize, length: usize) {
+    let first = (start - MEMORY_OFFSET + PAGE_SIZE - 1) / PAGE_SIZE;
+    let last = (start + length - MEMORY_OFFSET) / PAGE_SIZE;
+    if first < last {
+        BUDDY_ALLOCATOR.lock().add_range(first, last - first);
+    }
+}
+
+// 分配页帧的引用计数表，需要在加入可用的物理内存之后调用，使这张表从页帧而不是启动堆中分配
+pub fn init_mapped_frames(end: usize) {
+    let table = vec![0; (end - MEMORY_OFFSET) / PAGE_SIZE];
+    *MAPPED_FRAMES.lock() = table;
+}
+
+// 物理内存 [start, start + length) 被保留，与之有重叠的页帧都不会被分配
+pub fn reserve(start: usize, length: usize) {
+    let first = (start - MEMORY_OFFSET) / PAGE_SIZE;
+    let last = (start + length - MEMORY_OFFSET + PAGE_SIZE - 1) / PAGE_SIZE;
+    if first < last {
+        BUDDY_ALLOCATOR.lock().reserve(first, last - first);
+    }
 }
 
 pub fn alloc_frames(size: usize) -> Option<Frame> {
@@ -29,34 +72,111 @@ pub fn alloc_frames(size: usize) -> Option<Frame> {
     ret.map(|addr| Frame::of_addr(PhysAddr::new(addr)))
 }
 
-pub fn dealloc_frame(target: Frame) {
-    dealloc_frames(target, 1);
+// 释放错误的页帧说明内核中有 bug ，此时直接 panic 而不是破坏分配器的状态
+pub fn dealloc_frames(target: Frame, size: usize) {
+    let mut allocator = BUDDY_ALLOCATOR.lock();
+    let addr = target.start_address().as_usize();
+    if let Err(err) = allocator.dealloc(addr / PAGE_SIZE - MEMORY_OFFSET / PAGE_SIZE, size) {
+        panic!("dealloc {} frames at {:#x} failed: {}", size, addr, err);
+    }
+    // 在释放的页帧中填入特殊的值，使释放后仍被使用的页帧更容易被发现
+    #[cfg(feature = "frame-poison")]
+    unsafe {
+        core::ptr::write_bytes(
+            super::access_pa_via_va(addr) as *mut u8,
+            FRAME_POISON,
+            size * PAGE_SIZE,
+        );
+    }
 }
 
-pub fn dealloc_frames(target: Frame, size: usize) {
-    BUDDY_ALLOCATOR.lock().dealloc(
-        target.start_address().as_usize() / PAGE_SIZE - MEMORY_OFFSET / PAGE_SIZE,
-        size,
-    );
+// 供 riscv 库中的页表操作（如 map_to 创建二级页表）使用的物理页帧分配器
+// 分配出的页帧不再由 FrameTracker 持有，需要通过 dealloc 释放，或永久属于内核页表
+pub struct GlobalFrameAlloc;
+
+impl FrameAllocator for GlobalFrameAlloc {
+    fn alloc(&mut self) -> Option<Frame> {
+        FrameTracker::new(false).map(FrameTracker::into_frame)
+    }
+}
+
+impl FrameDeallocator for GlobalFrameAlloc {
+    fn dealloc(&mut self, frame: Frame) {
+        drop(unsafe { FrameTracker::from_frame(frame, 1) });
+    }
+}
+
+// 空闲的物理页帧数
+pub fn free_pages() -> usize {
+    BUDDY_ALLOCATOR.lock().free_pages()
+}
+
+// 能够一次分配的最多的连续物理页帧数
+pub fn largest_free_block() -> usize {
+    BUDDY_ALLOCATOR.lock().largest_free_block()
+}
+
+fn frame_index(target: Frame) -> usize {
+    target.number() - MEMORY_OFFSET / PAGE_SIZE
+}
+
+// 页帧将被映射到一个页表项中，此后由页表项持有，在 release_frame 中回收
+pub fn map_frame(frame: FrameTracker) -> Frame {
+    let target = frame.into_frame();
+    let mut table = MAPPED_FRAMES.lock();
+    let count = &mut table[frame_index(target)];
+    assert_eq!(*count, 0, "map a mapped frame!");
+    *count = 1;
+    target
+}
+
+// 页帧被多映射到了一个页表项中
+pub fn share_frame(target: Frame) {
+    let mut table = MAPPED_FRAMES.lock();
+    let count = &mut table[frame_index(target)];
+    assert!(*count > 0, "share an unmapped frame!");
+    *count += 1;
+}
+
+// 一个页表项不再映射该页帧，最后一个页表项解除映射时回收页帧
+pub fn release_frame(target: Frame) {
+    {
+        let mut table = MAPPED_FRAMES.lock();
+        let count = &mut table[frame_index(target)];
+        assert!(*count > 0, "release an unmapped frame!");
+        *count -= 1;
+        if *count > 0 {
+            return;
+        }
+    }
+    // 在锁外回收页帧
+    drop(unsafe { FrameTracker::from_frame(target, 1) });
+}
+
+// 映射了该页帧的页表项数
+pub fn frame_ref_count(target: Frame) -> usize {
+    MAPPED_FRAMES
+        .lock()
+        .get(frame_index(target))
+        .map_or(0, |&count| count)
 }
 
 pub fn test() {
-    let frame1: Frame = alloc_frame().expect("failed to alloc frame");
-    println!(
-        "test frame_allocator: {:#x}",
-        frame1.start_address().as_usize()
-    );
-    let frame2: Frame = alloc_frames(2).expect("failed to alloc frame");
-    println!(
-        "test frame_allocator: {:#x}",
-        frame2.start_address().as_usize()
-    );
-    let frame3: Frame = alloc_frame().expect("failed to alloc frame");
+    let free = free_pages();
+    {
+        let frame1 = FrameTracker::new(false).expect("failed to alloc frame");
+        println!("test frame_allocator: {:#x}", frame1.start_address());
+        let frame2 = FrameTracker::new_contiguous(2, false).expect("failed to alloc frame");
+        println!("test frame_allocator: {:#x}", frame2.start_address());
+        let frame3 = FrameTracker::new(true).expect("failed to alloc frame");
+        println!("test frame_allocator: {:#x}", frame3.start_address());
+        assert_eq!(free_pages(), free - 4);
+    }
+    // 离开作用域时页帧被自动释放
+    assert_eq!(free_pages(), free);
     println!(
-        "test frame_allocator: {:#x}",
-        frame3.start_address().as_usize()
+        "test frame_allocator: {} free pages, largest free block {} pages",
+        free_pages(),
+        largest_free_block()
     );
-    dealloc_frame(frame1);
-    dealloc_frames(frame2, 2);
-    dealloc_frame(frame3);
 }
@@ -0,0 +1,68 @@
+use super::{alloc_frames, dealloc_frames};
+use crate::consts::*;
+use crate::memory::access_pa_via_va;
+use core::mem;
+use core::ptr;
+use riscv::addr::Frame;
+
+// 拥有一段连续物理页帧的句柄，被 drop 时将页帧还给物理页帧分配器
+pub struct FrameTracker {
+    frame: Frame,
+    pages: usize,
+}
+
+impl FrameTracker {
+    // 分配一个物理页帧，zeroed 为 true 时将其清零
+    pub fn new(zeroed: bool) -> Option<FrameTracker> {
+        FrameTracker::new_contiguous(1, zeroed)
+    }
+
+    // 分配 pages 个连续的物理页帧
+    pub fn new_contiguous(pages: usize, zeroed: bool) -> Option<FrameTracker> {
+        let tracker = FrameTracker {
+            frame: alloc_frames(pages)?,
+            pages,
+        };
+        if zeroed {
+            tracker.zero();
+        }
+        Some(tracker)
+    }
+
+    // 接管一段由 into_frame 放弃所有权的物理页帧
+    pub unsafe fn from_frame(frame: Frame, pages: usize) -> FrameTracker {
+        FrameTracker { frame, pages }
+    }
+
+    pub fn frame(&self) -> Frame {
+        self.frame
+    }
+
+    // 起始物理地址
+    pub fn start_address(&self) -> usize {
+        self.frame.start_address().as_usize()
+    }
+
+    pub fn zero(&self) {
+        unsafe {
+            ptr::write_bytes(
+                access_pa_via_va(self.start_address()) as *mut u8,
+                0,
+                self.pages * PAGE_SIZE,
+            );
+        }
+    }
+
+    // 放弃所有权，之后由调用者（如页表项）负责释放这段页帧
+    pub fn into_frame(self) -> Frame {
+        let frame = self.frame;
+        mem::forget(self);
+        frame
+    }
+}
+
+impl Drop for FrameTracker {
+    fn drop(&mut self) {
+        dealloc_frames(self.frame, self.pages);
+    }
+}
@@ -0,0 +1,209 @@
+use super::access_pa_via_va;
+use super::frame_allocator::{alloc_frames, dealloc_frames, FRAME_ALLOCATOR_READY};
+use crate::consts::*;
+use crate::sync::IrqMutex;
+use core::alloc::{GlobalAlloc, Layout};
+use core::cmp::max;
+use core::fmt;
+use core::ptr;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use riscv::addr::{Frame, PhysAddr};
+
+// 由 slab 负责的对象大小为 8, 16, ..., 2048 字节，更大的分配直接使用连续的物理页帧
+const MIN_SLAB_SIZE: usize = 8;
+const MAX_SLAB_SIZE: usize = 2048;
+const SLAB_CLASSES: usize = 9;
+
+// 一种大小的对象的 slab ：从整页中切分出对象，空闲的对象通过其首个字串成链表
+struct Slab {
+    object_size: usize,
+    free: usize,      // 空闲链表的表头，为 0 表示链表为空
+    pages: usize,     // 占用的页数
+    allocated: usize, // 已分配出去的对象数
+}
+
+impl Slab {
+    const fn new(object_size: usize) -> Slab {
+        Slab {
+            object_size,
+            free: 0,
+            pages: 0,
+            allocated: 0,
+        }
+    }
+
+    fn alloc(&mut self) -> Option<usize> {
+        if self.free == 0 {
+            self.grow()?;
+        }
+        let object = self.free;
+        self.free = unsafe { ptr::read(object as *const usize) };
+        self.allocated += 1;
+        Some(object)
+    }
+
+    fn dealloc(&mut self, object: usize) {
+        self.push_free(object);
+        self.allocated -= 1;
+    }
+
+    fn push_free(&mut self, object: usize) {
+        unsafe {
+            ptr::write(object as *mut usize, self.free);
+        }
+        self.free = object;
+    }
+
+    // 空闲对象用完时再申请一页，全部切分为空闲对象
+    fn grow(&mut self) -> Option<()> {
+        let page = alloc_pages(1)?;
+        for object in (page..page + PAGE_SIZE).step_by(self.object_size).rev() {
+            self.push_free(object);
+        }
+        self.pages += 1;
+        Some(())
+    }
+}
+
+// 内核堆：小的分配由按大小分级的 slab 负责，大的分配直接向物理页帧分配器申请
+pub struct KernelHeap {
+    slabs: [IrqMutex<Slab>; SLAB_CLASSES],
+    large_pages: AtomicUsize, // 大块分配占用的页数
+}
+
+// 堆的使用情况
+#[derive(Clone, Copy, Debug)]
+pub struct HeapUsage {
+    pub in_use: usize,      // 已分配出去的字节数，按 slab 对象或整页计算
+    pub slab_pages: usize,  // slab 占用的页数
+    pub large_pages: usize, // 大块分配占用的页数
+    pub boot_used: usize,   // 启动堆中已被使用的字节数
+}
+
+impl fmt::Display for HeapUsage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} bytes in use, {} slab pages, {} large pages, {}/{} bytes of boot heap",
+            self.in_use, self.slab_pages, self.large_pages, self.boot_used, KERNEL_HEAP_SIZE
+        )
+    }
+}
+
+impl KernelHeap {
+    pub const fn new() -> KernelHeap {
+        KernelHeap {
+            slabs: [
+                IrqMutex::new(Slab::new(8)),
+                IrqMutex::new(Slab::new(16)),
+                IrqMutex::new(Slab::new(32)),
+                IrqMutex::new(Slab::new(64)),
+                IrqMutex::new(Slab::new(128)),
+                IrqMutex::new(Slab::new(256)),
+                IrqMutex::new(Slab::new(512)),
+                IrqMutex::new(Slab::new(1024)),
+                IrqMutex::new(Slab::new(2048)),
+            ],
+            large_pages: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn usage(&self) -> HeapUsage {
+        let mut usage = HeapUsage {
+            in_use: 0,
+            slab_pages: 0,
+            large_pages: self.large_pages.load(Ordering::Relaxed),
+            boot_used: *BOOT_HEAP_USED.lock(),
+        };
+        for slab in self.slabs.iter() {
+            let slab = slab.lock();
+            usage.in_use += slab.allocated * slab.object_size;
+            usage.slab_pages += slab.pages;
+        }
+        usage.in_use += usage.large_pages * PAGE_SIZE;
+        usage
+    }
+}
+
+pub fn usage() -> HeapUsage {
+    crate::HEAP_ALLOCATOR.usage()
+}
+
+// 对象的大小向上取整到 2 的幂，这样按大小对齐的对象同时满足了对齐要求
+fn slab_class(layout: &Layout) -> Option<usize> {
+    let size = max(max(layout.size(), layout.align()), MIN_SLAB_SIZE).next_power_of_two();
+    if size > MAX_SLAB_SIZE {
+        None
+    } else {
+        Some((size / MIN_SLAB_SIZE).trailing_zeros() as usize)
+    }
+}
+
+fn pages_of(layout: &Layout) -> usize {
+    (max(layout.size(), layout.align()) + PAGE_SIZE - 1) / PAGE_SIZE
+}
+
+unsafe impl GlobalAlloc for KernelHeap {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let addr = match slab_class(&layout) {
+            Some(class) => self.slabs[class].lock().alloc(),
+            None => {
+                let pages = pages_of(&layout);
+                let addr = alloc_pages(pages);
+                if addr.is_some() {
+                    self.large_pages.fetch_add(pages, Ordering::Relaxed);
+                }
+                addr
+            }
+        };
+        addr.unwrap_or(0) as *mut u8
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        match slab_class(&layout) {
+            Some(class) => self.slabs[class].lock().dealloc(ptr as usize),
+            None => {
+                let pages = pages_of(&layout);
+                self.large_pages.fetch_sub(pages, Ordering::Relaxed);
+                dealloc_pages(ptr as usize, pages);
+            }
+        }
+    }
+}
+
+// 启动时物理页帧分配器还没有初始化（它本身也需要堆），此时从这里分配页
+#[repr(align(4096))]
+struct BootHeap([u8; KERNEL_HEAP_SIZE]);
+
+static mut BOOT_HEAP: BootHeap = BootHeap([0; KERNEL_HEAP_SIZE]);
+static BOOT_HEAP_USED: IrqMutex<usize> = IrqMutex::new(0);
+
+fn boot_heap_start() -> usize {
+    unsafe { BOOT_HEAP.0.as_ptr() as usize }
+}
+
+// 分配 pages 个连续的页，返回其内核虚拟地址
+// 与物理页帧分配器一样，返回的地址按 pages 向上取整到 2 的幂的大小对齐
+// 物理页帧分配器建立之后只从它分配，物理内存耗尽时返回 None 而不是继续使用启动堆
+fn alloc_pages(pages: usize) -> Option<usize> {
+    if FRAME_ALLOCATOR_READY.load(Ordering::Acquire) {
+        return alloc_frames(pages).map(|frame| access_pa_via_va(frame.start_address().as_usize()));
+    }
+    let mut used = BOOT_HEAP_USED.lock();
+    let align = pages.next_power_of_two() * PAGE_SIZE;
+    let start = (boot_heap_start() + *used + align - 1) & !(align - 1);
+    if start + pages * PAGE_SIZE > boot_heap_start() + KERNEL_HEAP_SIZE {
+        return None;
+    }
+    *used = start + pages * PAGE_SIZE - boot_heap_start();
+    Some(start)
+}
+
+fn dealloc_pages(addr: usize, pages: usize) {
+    // 启动堆中的页只在启动时被分配给长期存在的对象，不回收
+    if boot_heap_start() <= addr && addr < boot_heap_start() + KERNEL_HEAP_SIZE {
+        return;
+    }
+    let pa = addr - PHYSICAL_MEMORY_OFFSET;
+    dealloc_frames(Frame::of_addr(PhysAddr::new(pa)), pages);
+}
@@ -0,0 +1,133 @@
+use super::handler::MemoryHandler;
+use super::MapError;
+use crate::consts::*;
+use crate::memory::frame_allocator::release_frame;
+use crate::memory::paging::ActivePageTable;
+use crate::memory::swap;
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::cmp::{max, min};
+use riscv::addr::{Frame, PhysAddr};
+use riscv::paging::PageTableFlags as EF;
+
+// 地址空间中的一段连续区域 [start, end) ，其中所有页的标志相同，并以同一种方式映射
+#[derive(Clone)]
+pub struct MemoryArea {
+    start: usize,
+    end: usize,
+    flags: EF,
+    handler: Box<dyn MemoryHandler>,
+}
+
+impl MemoryArea {
+    // 起止地址分别向下、向上对齐到页
+    pub fn new(start: usize, end: usize, flags: EF, handler: Box<dyn MemoryHandler>) -> MemoryArea {
+        MemoryArea {
+            start: start & !(PAGE_SIZE - 1),
+            end: (end + PAGE_SIZE - 1) & !(PAGE_SIZE - 1),
+            flags,
+            handler,
+        }
+    }
+
+    pub fn contains(&self, addr: usize) -> bool {
+        self.start <= addr && addr < self.end
+    }
+
+    pub fn is_overlap_with(&self, start: usize, end: usize) -> bool {
+        self.start < end && start < self.end
+    }
+
+    fn pages(&self) -> impl Iterator<Item = usize> {
+        (self.start..self.end).step_by(PAGE_SIZE)
+    }
+
+    // data 为 (起始地址, 内容) ，在映射每一页时写入其中属于该页的部分
+    // 有一页映射失败时，解除之前已映射的页
+    pub fn map(
+        &self,
+        pt: &mut ActivePageTable,
+        data: Option<(usize, &[u8])>,
+    ) -> Result<(), MapError> {
+        for va in self.pages() {
+            let page_data = data.and_then(|(start, data)| {
+                // data 在这一页中的部分为 [copy_start, copy_end)
+                let copy_start = max(va, start);
+                let copy_end = min(va + PAGE_SIZE, start + data.len());
+                if copy_start < copy_end {
+                    Some((copy_start - va, &data[copy_start - start..copy_end - start]))
+                } else {
+                    None
+                }
+            });
+            if let Err(err) = self.handler.map(pt, va, self.flags, page_data) {
+                for mapped in (self.start..va).step_by(PAGE_SIZE) {
+                    self.handler.unmap(pt, mapped);
+                }
+                return Err(err);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn unmap(&self, pt: &mut ActivePageTable) {
+        for va in self.pages() {
+            self.handler.unmap(pt, va);
+        }
+    }
+
+    // fork 时在父地址空间中处理该区域的每一页，返回子地址空间中要直接建立的映射
+    // 失败时放弃已经共享出去的页帧，父地址空间中的写时复制页在写入时会发现自己是唯一的使用者
+    pub fn share(&self, pt: &mut ActivePageTable) -> Result<Vec<(usize, usize, EF)>, MapError> {
+        let mut shared = Vec::new();
+        for va in self.pages() {
+            match self.handler.share(pt, va) {
+                Ok(Some((pa, flags))) => shared.push((va, pa, flags)),
+                Ok(None) => {}
+                Err(err) => {
+                    for (_, pa, _) in shared {
+                        release_frame(Frame::of_addr(PhysAddr::new(pa)));
+                    }
+                    return Err(err);
+                }
+            }
+        }
+        Ok(shared)
+    }
+
+    // 在子地址空间中建立该区域的映射， shared 为 share 的返回值
+    // 即使有页映射失败，也会映射所有共享的页，使它们随子地址空间一起被回收
+    // 共享的页本身映射失败时，直接放弃子地址空间对它的引用
+    pub fn map_shared(
+        &self,
+        pt: &mut ActivePageTable,
+        shared: &[(usize, usize, EF)],
+    ) -> Result<(), MapError> {
+        let mut ret = Ok(());
+        let mut shared = shared.iter().peekable();
+        for va in self.pages() {
+            match shared.peek() {
+                Some(&&(shared_va, pa, flags)) if shared_va == va => {
+                    match pt.map(va, pa, flags) {
+                        Ok(()) => swap::track(pt, va),
+                        Err(err) => {
+                            release_frame(Frame::of_addr(PhysAddr::new(pa)));
+                            if ret.is_ok() {
+                                ret = Err(err);
+                            }
+                        }
+                    }
+                    shared.next();
+                }
+                _ if ret.is_ok() => ret = self.handler.map(pt, va, self.flags, None),
+                _ => {}
+            }
+        }
+        ret
+    }
+
+    pub fn handle_page_fault(&self, pt: &mut ActivePageTable, addr: usize, store: bool) -> bool {
+        let va = addr & !(PAGE_SIZE - 1);
+        self.handler.handle_page_fault(pt, va, self.flags, store)
+    }
+}
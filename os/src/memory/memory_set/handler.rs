@@ -0,0 +1,280 @@
+use super::MapError;
+use crate::consts::*;
+use crate::memory::access_pa_via_va;
+use crate::memory::frame_allocator::{
+    frame_ref_count, map_frame, release_frame, share_frame, FrameTracker,
+};
+use crate::memory::paging::{ActivePageTable, COPY_ON_WRITE};
+use crate::memory::swap;
+use alloc::boxed::Box;
+use core::ptr;
+use riscv::addr::{Frame, PhysAddr};
+use riscv::paging::PageTableFlags as EF;
+
+// 描述一个区域中的虚拟页如何对应到物理页帧
+pub trait MemoryHandler: 'static {
+    fn box_clone(&self) -> Box<dyn MemoryHandler>;
+
+    // 建立 va 所在页的映射， data 为 (页内偏移, 内容) ，需要在该页可能被换出之前写入
+    // 不在映射时分配物理页帧的 handler 不支持 data ，返回 InvalidArea
+    fn map(
+        &self,
+        pt: &mut ActivePageTable,
+        va: usize,
+        flags: EF,
+        data: Option<(usize, &[u8])>,
+    ) -> Result<(), MapError>;
+
+    // 解除 va 所在页的映射，并回收该页独占的物理页帧
+    fn unmap(&self, pt: &mut ActivePageTable, va: usize);
+
+    // fork 时在父地址空间中处理 va 所在页，返回子地址空间中该页应映射到的物理地址和标志
+    // 返回 None 时子地址空间通过 map 重新建立映射
+    fn share(
+        &self,
+        _pt: &mut ActivePageTable,
+        _va: usize,
+    ) -> Result<Option<(usize, EF)>, MapError> {
+        Ok(None)
+    }
+
+    // 处理访问 va 所在页引发的页错误，返回是否已处理
+    fn handle_page_fault(
+        &self,
+        pt: &mut ActivePageTable,
+        va: usize,
+        flags: EF,
+        store: bool,
+    ) -> bool;
+}
+
+impl Clone for Box<dyn MemoryHandler> {
+    fn clone(&self) -> Box<dyn MemoryHandler> {
+        self.box_clone()
+    }
+}
+
+// 线性映射：虚拟地址 va 对应物理地址 va - offset ，不拥有物理页帧
+#[derive(Clone)]
+pub struct Linear {
+    offset: usize,
+}
+
+impl Linear {
+    pub fn new(offset: usize) -> Linear {
+        Linear { offset }
+    }
+}
+
+impl MemoryHandler for Linear {
+    fn box_clone(&self) -> Box<dyn MemoryHandler> {
+        Box::new(self.clone())
+    }
+
+    fn map(
+        &self,
+        pt: &mut ActivePageTable,
+        va: usize,
+        flags: EF,
+        data: Option<(usize, &[u8])>,
+    ) -> Result<(), MapError> {
+        if data.is_some() {
+            return Err(MapError::InvalidArea);
+        }
+        pt.map(va, va - self.offset, flags)
+    }
+
+    fn unmap(&self, pt: &mut ActivePageTable, va: usize) {
+        pt.unmap(va);
+    }
+
+    fn handle_page_fault(&self, _: &mut ActivePageTable, _: usize, _: EF, _: bool) -> bool {
+        false
+    }
+}
+
+// 每一页在映射时就分配一个清零的物理页帧，物理内存不足时可以被换出
+#[derive(Clone)]
+pub struct ByFrame;
+
+impl ByFrame {
+    pub fn new() -> ByFrame {
+        ByFrame
+    }
+}
+
+impl MemoryHandler for ByFrame {
+    fn box_clone(&self) -> Box<dyn MemoryHandler> {
+        Box::new(self.clone())
+    }
+
+    fn map(
+        &self,
+        pt: &mut ActivePageTable,
+        va: usize,
+        flags: EF,
+        data: Option<(usize, &[u8])>,
+    ) -> Result<(), MapError> {
+        let frame = alloc_zeroed_frame().ok_or(MapError::OutOfMemory)?;
+        // 页帧还未被 swap 跟踪，不会在写入前被换出
+        if let Some((offset, data)) = data {
+            unsafe {
+                ptr::copy_nonoverlapping(
+                    data.as_ptr(),
+                    (access_pa_via_va(frame.start_address()) + offset) as *mut u8,
+                    data.len(),
+                );
+            }
+        }
+        pt.map(va, frame.start_address(), flags)?;
+        map_frame(frame);
+        swap::track(pt, va);
+        Ok(())
+    }
+
+    fn unmap(&self, pt: &mut ActivePageTable, va: usize) {
+        unmap_frame(pt, va);
+    }
+
+    fn share(&self, pt: &mut ActivePageTable, va: usize) -> Result<Option<(usize, EF)>, MapError> {
+        share_cow(pt, va)
+    }
+
+    fn handle_page_fault(&self, pt: &mut ActivePageTable, va: usize, _: EF, store: bool) -> bool {
+        swap::swap_in(pt, va) || (store && handle_cow(pt, va))
+    }
+}
+
+// 按需分配：映射时什么也不做，第一次访问时才在页错误中分配清零的物理页帧
+#[derive(Clone)]
+pub struct Lazy;
+
+impl Lazy {
+    pub fn new() -> Lazy {
+        Lazy
+    }
+}
+
+impl MemoryHandler for Lazy {
+    fn box_clone(&self) -> Box<dyn MemoryHandler> {
+        Box::new(self.clone())
+    }
+
+    fn map(
+        &self,
+        _: &mut ActivePageTable,
+        _: usize,
+        _: EF,
+        data: Option<(usize, &[u8])>,
+    ) -> Result<(), MapError> {
+        if data.is_some() {
+            return Err(MapError::InvalidArea);
+        }
+        Ok(())
+    }
+
+    fn unmap(&self, pt: &mut ActivePageTable, va: usize) {
+        unmap_frame(pt, va);
+    }
+
+    fn share(&self, pt: &mut ActivePageTable, va: usize) -> Result<Option<(usize, EF)>, MapError> {
+        share_cow(pt, va)
+    }
+
+    fn handle_page_fault(
+        &self,
+        pt: &mut ActivePageTable,
+        va: usize,
+        flags: EF,
+        store: bool,
+    ) -> bool {
+        if swap::is_swapped(pt, va) {
+            return swap::swap_in(pt, va);
+        }
+        if pt.translate(va).is_none() {
+            // 物理内存耗尽时无法处理，由调用者结束进程
+            let frame = match alloc_zeroed_frame() {
+                Some(frame) => frame,
+                None => return false,
+            };
+            if pt.map(va, frame.start_address(), flags).is_err() {
+                return false;
+            }
+            map_frame(frame);
+            swap::track(pt, va);
+            return true;
+        }
+        store && handle_cow(pt, va)
+    }
+}
+
+fn frame_of(pa: usize) -> Frame {
+    Frame::of_addr(PhysAddr::new(pa))
+}
+
+// 解除一个拥有物理页帧的页的映射，该页可能已被换出或从未被访问过
+fn unmap_frame(pt: &mut ActivePageTable, va: usize) {
+    if swap::discard(pt, va) || pt.translate(va).is_none() {
+        return;
+    }
+    swap::untrack(pt, va);
+    release_frame(frame_of(pt.unmap(va)));
+}
+
+// 映射成功后通过 map_frame 将页帧交给页表项持有
+fn alloc_zeroed_frame() -> Option<FrameTracker> {
+    let frame = swap::alloc_frame()?;
+    frame.zero();
+    Some(frame)
+}
+
+// 让父子地址空间以只读方式共享 va 所在页的物理页帧
+// 可写的页被标记为 COPY_ON_WRITE ，直到其中一方写入时才在页错误中复制
+// 已被换出的页先换入，再与子地址空间共享；没有物理内存用来换入时返回 OutOfMemory
+fn share_cow(pt: &mut ActivePageTable, va: usize) -> Result<Option<(usize, EF)>, MapError> {
+    if swap::is_swapped(pt, va) && !swap::swap_in(pt, va) {
+        return Err(MapError::OutOfMemory);
+    }
+    let (pa, flags) = match pt.translate(va) {
+        Some(entry) => entry,
+        None => return Ok(None),
+    };
+    let flags = if flags.contains(EF::WRITABLE) {
+        let flags = (flags - EF::WRITABLE) | COPY_ON_WRITE;
+        pt.remap(va, pa, flags);
+        flags
+    } else {
+        flags
+    };
+    share_frame(frame_of(pa));
+    Ok(Some((pa, flags)))
+}
+
+// 处理对写时复制页的写入，返回是否已处理
+// 页帧仍被共享时复制一份私有的，否则直接恢复写权限；物理内存耗尽时返回 false
+fn handle_cow(pt: &mut ActivePageTable, va: usize) -> bool {
+    let (pa, flags) = match pt.translate(va) {
+        Some((pa, flags)) if flags.contains(COPY_ON_WRITE) => (pa, flags),
+        _ => return false,
+    };
+    let flags = (flags - COPY_ON_WRITE) | EF::WRITABLE;
+    if frame_ref_count(frame_of(pa)) == 1 {
+        pt.remap(va, pa, flags);
+        return true;
+    }
+    let frame = match swap::alloc_frame() {
+        Some(frame) => frame,
+        None => return false,
+    };
+    unsafe {
+        ptr::copy_nonoverlapping(
+            access_pa_via_va(pa) as *const u8,
+            access_pa_via_va(frame.start_address()) as *mut u8,
+            PAGE_SIZE,
+        );
+    }
+    pt.remap(va, frame.start_address(), flags);
+    map_frame(frame);
+    release_frame(frame_of(pa));
+    true
+}
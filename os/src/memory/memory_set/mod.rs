@@ -0,0 +1,111 @@
+mod area;
+pub mod handler;
+
+use super::paging::InactivePageTable;
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use area::MemoryArea;
+use handler::MemoryHandler;
+use riscv::paging::PageTableFlags as EF;
+
+#[derive(Debug)]
+pub enum MapError {
+    // 区域为空，或要写入的数据超出了区域
+    InvalidArea,
+    // 区域与已有的区域重叠
+    Overlapping,
+    // 没有足够的物理页帧
+    OutOfMemory,
+}
+
+// 一个地址空间：一张页表以及其中已映射的若干区域
+// 内核部分的映射来自创建时的当前页表，不属于任何区域
+pub struct MemorySet {
+    areas: Vec<MemoryArea>,
+    page_table: InactivePageTable,
+}
+
+impl MemorySet {
+    // 创建一个只有内核映射的地址空间
+    pub fn new() -> MemorySet {
+        MemorySet {
+            areas: Vec::new(),
+            page_table: InactivePageTable::new(),
+        }
+    }
+
+    // 创建一个空的地址空间，用于重新映射内核
+    pub fn new_kernel() -> MemorySet {
+        MemorySet {
+            areas: Vec::new(),
+            page_table: InactivePageTable::new_bare(),
+        }
+    }
+
+    // 加入区域 [start, end) 并建立映射，若给出 data 则从 start 开始写入
+    // 写入 data 的部分必须由 map 时就分配了物理页帧的 handler 映射，否则返回 InvalidArea
+    pub fn push(
+        &mut self,
+        start: usize,
+        end: usize,
+        flags: EF,
+        handler: impl MemoryHandler,
+        data: Option<&[u8]>,
+    ) -> Result<(), MapError> {
+        if start >= end || data.map_or(false, |data| data.len() > end - start) {
+            return Err(MapError::InvalidArea);
+        }
+        if self
+            .areas
+            .iter()
+            .any(|area| area.is_overlap_with(start, end))
+        {
+            return Err(MapError::Overlapping);
+        }
+        let area = MemoryArea::new(start, end, flags, Box::new(handler));
+        let data = data.map(|data| (start, data));
+        self.page_table.edit(|pt| area.map(pt, data))?;
+        self.areas.push(area);
+        Ok(())
+    }
+
+    // 复制一份地址空间，其中的物理页帧在父子之间写时复制共享
+    // 物理内存不足时返回 OutOfMemory ，已经复制的部分随子地址空间一起被回收
+    pub fn fork(&mut self) -> Result<MemorySet, MapError> {
+        let mut new_set = MemorySet::new();
+        for area in self.areas.iter() {
+            let shared = self.page_table.edit(|pt| area.share(pt))?;
+            let ret = new_set.page_table.edit(|pt| area.map_shared(pt, &shared));
+            new_set.areas.push(area.clone());
+            ret?;
+        }
+        Ok(new_set)
+    }
+
+    // 处理访问 addr 引发的页错误， store 表示是否为写入，返回是否已处理
+    pub fn handle_page_fault(&mut self, addr: usize, store: bool) -> bool {
+        let area = match self.areas.iter().find(|area| area.contains(addr)) {
+            Some(area) => area,
+            None => return false,
+        };
+        self.page_table
+            .edit(|pt| area.handle_page_fault(pt, addr, store))
+    }
+
+    // 写入 satp 寄存器的值
+    pub fn token(&self) -> usize {
+        self.page_table.token()
+    }
+
+    pub unsafe fn activate(&self) {
+        self.page_table.activate();
+    }
+}
+
+impl Drop for MemorySet {
+    fn drop(&mut self) {
+        for area in self.areas.drain(..) {
+            self.page_table.edit(|pt| area.unmap(pt));
+        }
+    }
+}
@@ -1,31 +1,161 @@
 pub mod frame_allocator;
+pub mod heap;
+pub mod memory_set;
+pub mod paging;
+pub mod slab;
+pub mod swap;
 
 use crate::consts::*;
-use crate::HEAP_ALLOCATOR;
-use frame_allocator::{init as init_frame_allocator, test as test_frame_allocator};
+use crate::fdt::Fdt;
+use crate::init::BootInfo;
+use core::cmp::{max, min};
+use frame_allocator::{
+    add_range as add_frame_range, init as init_frame_allocator, init_mapped_frames,
+    reserve as reserve_frames, test as test_frame_allocator,
+};
+use memory_set::{handler::Linear, MemorySet};
+use paging::ActivePageTable;
+use riscv::paging::PageTableFlags as EF;
 use riscv::register::sstatus;
+use spin::Mutex;
 
-pub fn init() {
+// 可用的物理内存从设备树中获得
+pub fn init(boot_info: &BootInfo) {
+    let dtb = boot_info.dtb;
     unsafe {
         sstatus::set_sum(); // Allow user memory access
     }
-    init_heap();
-    let memory_start = (end as usize - KERNEL_OFFSET + MEMORY_OFFSET) + PAGE_SIZE;
-    let memory_size = MEMORY_END - memory_start;
-    init_frame_allocator(memory_start, memory_size);
+    let fdt = unsafe { Fdt::from_addr(access_pa_via_va(dtb)) }.expect("invalid device tree!");
+    let memory_end = memory_regions(&fdt, MEMORY_END_LIMIT)
+        .map(|(_, end)| end)
+        .max()
+        .expect("no memory in device tree!");
+    println!("physical memory: [{:#x}, {:#x})", MEMORY_OFFSET, memory_end);
+    init_frame_allocator(memory_end);
+    for (start, end) in memory_regions(&fdt, memory_end) {
+        add_frame_range(start, end - start);
+    }
+    for (start, end) in reserved_regions(&fdt, memory_end) {
+        reserve_frames(start, end - start);
+    }
+    // 设备树本身在之后还要使用
+    if let Some((start, end)) = clip(dtb as u64, (dtb + fdt.total_size()) as u64, memory_end) {
+        reserve_frames(start, end - start);
+    }
+    // OpenSBI 与内核镜像所在的内存
+    reserve_frames(MEMORY_OFFSET, boot_info.kernel_phys.end - MEMORY_OFFSET);
+    init_mapped_frames(memory_end);
     test_frame_allocator();
+    paging::init();
+    remap_kernel(memory_end);
 }
 
-fn init_heap() {
-    static mut HEAP: [u8; KERNEL_HEAP_SIZE] = [0; KERNEL_HEAP_SIZE];
+// 只保留物理内存区间 [start, end) 中位于 [MEMORY_OFFSET, limit) 的部分
+fn clip(start: u64, end: u64, limit: usize) -> Option<(usize, usize)> {
+    let start = max(start, MEMORY_OFFSET as u64);
+    let end = min(end, limit as u64);
+    if start < end {
+        Some((start as usize, end as usize))
+    } else {
+        None
+    }
+}
+
+// 设备树中 /memory 节点描述的物理内存
+fn memory_regions(fdt: &Fdt, limit: usize) -> impl Iterator<Item = (usize, usize)> {
+    fdt.root()
+        .children()
+        .filter(|node| node.base_name() == "memory")
+        .flat_map(|node| node.reg())
+        .filter_map(move |(start, size)| clip(start, start.saturating_add(size), limit))
+}
+
+// 设备树的内存保留块与 /reserved-memory 节点中不能被分配的物理内存
+fn reserved_regions(fdt: &Fdt, limit: usize) -> impl Iterator<Item = (usize, usize)> {
+    let reserved_memory = fdt.find("/reserved-memory");
+    fdt.mem_reserves()
+        .chain(
+            reserved_memory
+                .into_iter()
+                .flat_map(|node| node.children())
+                .flat_map(|node| node.reg()),
+        )
+        .filter_map(move |(start, size)| clip(start, start.saturating_add(size), limit))
+}
+
+// 按照链接脚本中各段的属性重新映射内核，并切换到新的页表
+// 启动时的页表将整个内核映射为可读可写可执行，重新映射后写入代码段会引发页错误
+fn remap_kernel(memory_end: usize) {
+    let mut memory_set = MemorySet::new_kernel();
+    let offset = PHYSICAL_MEMORY_OFFSET;
+    let rw = EF::VALID | EF::READABLE | EF::WRITABLE;
+    let regions = [
+        (
+            stext as usize,
+            etext as usize,
+            EF::VALID | EF::READABLE | EF::EXECUTABLE,
+        ),
+        (srodata as usize, erodata as usize, EF::VALID | EF::READABLE),
+        (sdata as usize, edata as usize, rw),
+        // 启动栈
+        (edata as usize, sbss as usize, rw),
+        (sbss as usize, ebss as usize, rw),
+        // 内核之外的物理内存
+        (access_pa_via_va(MEMORY_OFFSET), stext as usize, rw),
+        (ebss as usize, access_pa_via_va(memory_end), rw),
+    ];
+    for &(start, end, flags) in regions.iter() {
+        memory_set
+            .push(start, end, flags, Linear::new(offset), None)
+            .expect("remap kernel failed!");
+    }
     unsafe {
-        HEAP_ALLOCATOR
-            .lock()
-            .init(HEAP.as_ptr() as usize, KERNEL_HEAP_SIZE);
+        memory_set.activate();
+    }
+    // 内核的地址空间在整个运行期间都不会被回收
+    core::mem::forget(memory_set);
+    println!("++++remap kernel succeed!++++");
+}
+
+// MMIO 区域中下一个可用的虚拟地址
+static MMIO_NEXT: Mutex<usize> = Mutex::new(MMIO_OFFSET);
+
+// 将设备的 MMIO 区间 [pa, pa + size) 映射到内核地址空间的 MMIO 区域，返回 pa 对应的虚拟地址
+// 设备不在线性映射覆盖的物理内存中，需要通过这里访问；MMIO 区域用完或物理内存不足时返回 None
+// 新建的二级页表只会出现在之后创建的地址空间中，因此要在创建用户进程之前调用
+pub fn map_mmio(pa: usize, size: usize) -> Option<usize> {
+    let start = pa & !(PAGE_SIZE - 1);
+    let end = pa.checked_add(size)?.checked_add(PAGE_SIZE - 1)? & !(PAGE_SIZE - 1);
+    let va = {
+        let mut next = MMIO_NEXT.lock();
+        if end - start > MMIO_OFFSET + MMIO_SIZE - *next {
+            return None;
+        }
+        // 映射失败时也不再使用这段虚拟地址，其中可能已有部分页被映射
+        *next += end - start;
+        *next - (end - start)
+    };
+    let mut pt = unsafe { ActivePageTable::new() };
+    let flags = EF::VALID | EF::READABLE | EF::WRITABLE;
+    for offset in (0..end - start).step_by(PAGE_SIZE) {
+        pt.map_kernel(va + offset, start + offset, flags).ok()?;
     }
+    Some(va + pa - start)
+}
+
+// 内核通过线性映射访问物理地址 pa
+pub fn access_pa_via_va(pa: usize) -> usize {
+    pa + PHYSICAL_MEMORY_OFFSET
 }
 
 // Symbols provided by linker script
 extern "C" {
-    fn end();
+    fn stext();
+    fn etext();
+    fn srodata();
+    fn erodata();
+    fn sdata();
+    fn edata();
+    fn sbss();
+    fn ebss();
 }
@@ -0,0 +1,217 @@
+use super::access_pa_via_va;
+use super::frame_allocator::{FrameTracker, GlobalFrameAlloc};
+use super::memory_set::MapError;
+use super::swap;
+use crate::consts::*;
+use alloc::vec::Vec;
+use riscv::addr::*;
+use riscv::asm::{sfence_vma, sfence_vma_all};
+use riscv::paging::{
+    FrameAllocator, MapToError, Mapper, PageTable, PageTableEntry, PageTableFlags as EF,
+    RecursivePageTable,
+};
+use riscv::register::satp;
+
+// 页表项中由软件使用的一位，标记该页是写时复制的共享页
+pub const COPY_ON_WRITE: EF = EF::RESERVED1;
+
+// 通过递归页表访问当前页表时，根页表所在的虚拟地址 (R, R+1, 0)
+const ROOT_PAGE_TABLE: *mut PageTable =
+    ((RECURSIVE_INDEX << 12 << 10) | ((RECURSIVE_INDEX + 1) << 12)) as *mut PageTable;
+
+// 为启动时使用的页表加上递归映射，之后内核才能通过 RecursivePageTable 修改页表
+pub fn init() {
+    let frame = satp::read().frame();
+    root_table_of(frame).set_recursive(RECURSIVE_INDEX, frame);
+    unsafe {
+        sfence_vma_all();
+    }
+}
+
+fn root_table_of(frame: Frame) -> &'static mut PageTable {
+    unsafe { &mut *(access_pa_via_va(frame.start_address().as_usize()) as *mut PageTable) }
+}
+
+// 将当前页表的递归项临时指向 satp 值为 token 的页表，从而可以像修改当前页表一样修改它
+// 可以嵌套使用，返回时恢复原来的递归项
+// 只能修改已有的二级页表：新建的二级页表没有所有者，需要通过 InactivePageTable::edit 修改
+pub fn edit_by_token<T>(token: usize, f: impl FnOnce(&mut ActivePageTable) -> T) -> T {
+    let (ret, tables) = edit_table(token, f);
+    assert!(tables.is_empty(), "page tables created by edit_by_token!");
+    ret
+}
+
+// 同时返回修改期间新建的二级页表
+fn edit_table<T>(
+    token: usize,
+    f: impl FnOnce(&mut ActivePageTable) -> T,
+) -> (T, Vec<FrameTracker>) {
+    let active = root_table_of(satp::read().frame());
+    let backup: PageTableEntry = active[RECURSIVE_INDEX];
+    let frame = Frame::of_addr(PhysAddr::new((token & 0x3f_ffff) << 12));
+    active[RECURSIVE_INDEX].set(frame, EF::VALID);
+    unsafe {
+        sfence_vma_all();
+    }
+    let mut pt = unsafe { ActivePageTable::new() };
+    let ret = f(&mut pt);
+    active[RECURSIVE_INDEX] = backup;
+    unsafe {
+        sfence_vma_all();
+    }
+    (ret, pt.new_tables)
+}
+
+// 为二级页表分配页帧，并记录下来交给页表的所有者
+// 物理内存不足时先换出一个用户页，换出时会通过 edit_by_token 嵌套修改页表
+struct TableAlloc<'a>(&'a mut Vec<FrameTracker>);
+
+impl FrameAllocator for TableAlloc<'_> {
+    fn alloc(&mut self) -> Option<Frame> {
+        let table = swap::alloc_frame()?;
+        let frame = table.frame();
+        self.0.push(table);
+        Some(frame)
+    }
+}
+
+fn map_to(
+    table: &mut RecursivePageTable<'static>,
+    va: usize,
+    pa: usize,
+    flags: EF,
+    allocator: &mut impl FrameAllocator,
+) -> Result<(), MapError> {
+    let page = Page::of_addr(VirtAddr::new(va));
+    let frame = Frame::of_addr(PhysAddr::new(pa));
+    match table.map_to(page, frame, flags, allocator) {
+        Ok(flush) => {
+            flush.flush();
+            Ok(())
+        }
+        Err(MapToError::FrameAllocationFailed) => Err(MapError::OutOfMemory),
+        Err(err) => panic!("map page {:#x} failed: {:?}", va, err),
+    }
+}
+
+// 当前正在使用（satp 指向）的页表
+pub struct ActivePageTable {
+    table: RecursivePageTable<'static>,
+    new_tables: Vec<FrameTracker>, // 建立映射时新建的二级页表
+}
+
+impl ActivePageTable {
+    pub unsafe fn new() -> ActivePageTable {
+        ActivePageTable {
+            table: RecursivePageTable::new_unchecked(&mut *ROOT_PAGE_TABLE, RECURSIVE_INDEX),
+            new_tables: Vec::new(),
+        }
+    }
+
+    // 没有物理页帧用来新建二级页表时返回 OutOfMemory
+    pub fn map(&mut self, va: usize, pa: usize, flags: EF) -> Result<(), MapError> {
+        map_to(
+            &mut self.table,
+            va,
+            pa,
+            flags,
+            &mut TableAlloc(&mut self.new_tables),
+        )
+    }
+
+    // 建立内核地址空间中的映射，新建的二级页表永久属于内核页表
+    pub fn map_kernel(&mut self, va: usize, pa: usize, flags: EF) -> Result<(), MapError> {
+        map_to(&mut self.table, va, pa, flags, &mut GlobalFrameAlloc)
+    }
+
+    // 解除 va 所在页的映射，返回它原来对应的物理地址
+    pub fn unmap(&mut self, va: usize) -> usize {
+        let page = Page::of_addr(VirtAddr::new(va));
+        let (frame, flush) = self.table.unmap(page).expect("unmap an unmapped page!");
+        flush.flush();
+        frame.start_address().as_usize()
+    }
+
+    // 修改一个已映射的页所对应的物理页帧和标志
+    pub fn remap(&mut self, va: usize, pa: usize, flags: EF) {
+        let page = Page::of_addr(VirtAddr::new(va));
+        let entry = self.table.ref_entry(page).expect("remap an unmapped page!");
+        entry.set(Frame::of_addr(PhysAddr::new(pa)), flags);
+        unsafe {
+            sfence_vma(0, va);
+        }
+    }
+
+    // va 所在页的页表项，二级页表不存在时返回 None
+    pub fn entry(&mut self, va: usize) -> Option<&mut PageTableEntry> {
+        let page = Page::of_addr(VirtAddr::new(va));
+        self.table.ref_entry(page).ok()
+    }
+
+    // 这个页表写入 satp 寄存器的值
+    pub fn token(&self) -> usize {
+        unsafe { (*ROOT_PAGE_TABLE)[RECURSIVE_INDEX].ppn() | (1 << 31) }
+    }
+
+    // 查询虚拟地址 va 所在页的页表项标志，未映射时返回 None
+    pub fn flags(&mut self, va: usize) -> Option<EF> {
+        self.translate(va).map(|(_, flags)| flags)
+    }
+
+    // 查询 va 所在页对应的物理页起始地址和页表项标志，未映射时返回 None
+    pub fn translate(&mut self, va: usize) -> Option<(usize, EF)> {
+        let page = Page::of_addr(VirtAddr::new(va));
+        let entry = self.table.ref_entry(page).ok()?;
+        if entry.flags().contains(EF::VALID) {
+            Some((entry.addr().as_usize(), entry.flags()))
+        } else {
+            None
+        }
+    }
+}
+
+// 一个未被激活的页表，可以作为一个进程的地址空间
+// 内核部分的一级页表项从当前页表中拷贝，因此所有地址空间共享内核的映射
+// 页表被 drop 时回收根页表与通过 edit 新建的二级页表，共享的内核部分的二级页表不属于它
+pub struct InactivePageTable {
+    root_frame: FrameTracker,
+    tables: Vec<FrameTracker>,
+}
+
+impl InactivePageTable {
+    pub fn new() -> InactivePageTable {
+        let page_table = InactivePageTable::new_bare();
+        let table = root_table_of(page_table.root_frame.frame());
+        let active = root_table_of(satp::read().frame());
+        for i in (KERNEL_OFFSET >> 22)..RECURSIVE_INDEX {
+            table[i] = active[i];
+        }
+        page_table
+    }
+
+    // 创建一个只有递归映射的页表，用于建立内核自己的页表
+    pub fn new_bare() -> InactivePageTable {
+        let root_frame = FrameTracker::new(true).expect("alloc root page table failed!");
+        root_table_of(root_frame.frame()).set_recursive(RECURSIVE_INDEX, root_frame.frame());
+        InactivePageTable {
+            root_frame,
+            tables: Vec::new(),
+        }
+    }
+
+    pub fn edit<T>(&mut self, f: impl FnOnce(&mut ActivePageTable) -> T) -> T {
+        let (ret, mut tables) = edit_table(self.token(), f);
+        self.tables.append(&mut tables);
+        ret
+    }
+
+    // 写入 satp 寄存器的值
+    pub fn token(&self) -> usize {
+        self.root_frame.frame().number() | (1 << 31)
+    }
+
+    pub unsafe fn activate(&self) {
+        satp::set(satp::Mode::Sv32, 0, self.root_frame.frame().number());
+        sfence_vma_all();
+    }
+}
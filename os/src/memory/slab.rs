@@ -0,0 +1,240 @@
+use super::access_pa_via_va;
+use super::frame_allocator::{alloc_frames, dealloc_frames};
+use crate::consts::*;
+use crate::sync::IrqMutex;
+use alloc::vec::Vec;
+use core::fmt;
+use core::marker::PhantomData;
+use core::mem::{align_of, size_of};
+use core::ops::{Deref, DerefMut};
+use core::ptr::{self, NonNull};
+use riscv::addr::{Frame, PhysAddr};
+
+// 每个 slab 至少能容纳的对象数
+const MIN_OBJECTS_PER_SLAB: usize = 8;
+
+// 固定大小内核对象的缓存
+// 对象从专用的连续物理页（slab）中分配，释放后留在缓存中供下次使用，不会在全局堆中产生碎片
+// 带有构造函数的缓存在对象被释放时不析构它，下次通过 get 可以直接复用已构造好的对象，
+// 直到 shrink 回收内存时才析构
+pub struct ObjectCache<T> {
+    name: &'static str,
+    ctor: Option<fn() -> Option<T>>,
+    inner: IrqMutex<CacheInner>,
+    _marker: PhantomData<T>,
+}
+
+// 缓存只保存对象的地址，对象本身由 Cached 持有
+unsafe impl<T> Send for ObjectCache<T> {}
+unsafe impl<T> Sync for ObjectCache<T> {}
+
+struct CacheInner {
+    slabs: Vec<usize>,       // 每个 slab 的起始地址
+    free: Vec<usize>,        // 未构造的空闲对象
+    constructed: Vec<usize>, // 已构造、可以直接复用的空闲对象
+    allocs: usize,           // 累计分配次数
+    frees: usize,            // 累计释放次数
+}
+
+// 一个缓存的统计信息
+#[derive(Clone, Copy, Debug)]
+pub struct CacheStats {
+    pub name: &'static str,
+    pub object_size: usize,
+    pub objects_per_slab: usize,
+    pub slabs: usize,       // 占用的 slab 数
+    pub active: usize,      // 正在使用的对象数
+    pub constructed: usize, // 已构造、等待复用的对象数
+    pub allocs: usize,
+    pub frees: usize,
+}
+
+impl fmt::Display for CacheStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}: {} active, {} cached, {} slabs of {} x {} bytes, {} allocs, {} frees",
+            self.name,
+            self.active,
+            self.constructed,
+            self.slabs,
+            self.objects_per_slab,
+            self.object_size,
+            self.allocs,
+            self.frees
+        )
+    }
+}
+
+impl<T> ObjectCache<T> {
+    // 对象在释放时被析构
+    pub fn new(name: &'static str) -> ObjectCache<T> {
+        ObjectCache {
+            name,
+            ctor: None,
+            inner: IrqMutex::new(CacheInner {
+                slabs: Vec::new(),
+                free: Vec::new(),
+                constructed: Vec::new(),
+                allocs: 0,
+                frees: 0,
+            }),
+            _marker: PhantomData,
+        }
+    }
+
+    // 对象由 ctor 构造，释放后保持构造好的状态以供复用； ctor 在内存不足时返回 None
+    pub fn with_ctor(name: &'static str, ctor: fn() -> Option<T>) -> ObjectCache<T> {
+        let mut cache = ObjectCache::new(name);
+        cache.ctor = Some(ctor);
+        cache
+    }
+
+    fn object_size() -> usize {
+        let align = align_of::<T>();
+        (size_of::<T>().max(1) + align - 1) / align * align
+    }
+
+    // slab 的页数为 2 的幂，与物理页帧分配器的分配粒度一致
+    fn slab_pages() -> usize {
+        let bytes = Self::object_size() * MIN_OBJECTS_PER_SLAB;
+        ((bytes + PAGE_SIZE - 1) / PAGE_SIZE).next_power_of_two()
+    }
+
+    fn objects_per_slab() -> usize {
+        Self::slab_pages() * PAGE_SIZE / Self::object_size()
+    }
+
+    // 申请一个新的 slab ，并将其中的对象全部加入未构造的空闲对象，物理内存不足时返回 None
+    fn grow(inner: &mut CacheInner) -> Option<()> {
+        let frame = alloc_frames(Self::slab_pages())?;
+        let start = access_pa_via_va(frame.start_address().as_usize());
+        inner.slabs.push(start);
+        for i in (0..Self::objects_per_slab()).rev() {
+            inner.free.push(start + i * Self::object_size());
+        }
+        Some(())
+    }
+
+    // 将 value 放入缓存中的一个空闲对象，没有空闲对象且无法申请新的 slab 时返回 None
+    // 只剩已构造的对象时，析构其中一个来存放 value
+    pub fn alloc(&'static self, value: T) -> Option<Cached<T>> {
+        let mut inner = self.inner.lock();
+        let addr = match inner.free.pop() {
+            Some(addr) => addr,
+            None => match inner.constructed.pop() {
+                Some(addr) => {
+                    unsafe { ptr::drop_in_place(addr as *mut T) };
+                    addr
+                }
+                None => {
+                    Self::grow(&mut inner)?;
+                    inner.free.pop().unwrap()
+                }
+            },
+        };
+        inner.allocs += 1;
+        unsafe {
+            ptr::write(addr as *mut T, value);
+        }
+        Some(Cached::new(self, addr))
+    }
+
+    // 取出一个已构造的对象，没有可复用的对象时用构造函数构造一个，内存不足时返回 None
+    pub fn get(&'static self) -> Option<Cached<T>> {
+        let ctor = self.ctor.expect("object cache has no constructor!");
+        let mut inner = self.inner.lock();
+        if let Some(addr) = inner.constructed.pop() {
+            inner.allocs += 1;
+            return Some(Cached::new(self, addr));
+        }
+        // 构造函数可能会使用堆，不能持有锁
+        drop(inner);
+        self.alloc(ctor()?)
+    }
+
+    fn free(&self, addr: usize) {
+        let mut inner = self.inner.lock();
+        inner.frees += 1;
+        if self.ctor.is_some() {
+            inner.constructed.push(addr);
+        } else {
+            unsafe { ptr::drop_in_place(addr as *mut T) };
+            inner.free.push(addr);
+        }
+    }
+
+    // 析构所有等待复用的对象，并将其中对象全部空闲的 slab 还给物理页帧分配器
+    pub fn shrink(&self) {
+        let mut inner = self.inner.lock();
+        while let Some(addr) = inner.constructed.pop() {
+            unsafe { ptr::drop_in_place(addr as *mut T) };
+            inner.free.push(addr);
+        }
+        let slab_size = Self::slab_pages() * PAGE_SIZE;
+        let mut i = 0;
+        while i < inner.slabs.len() {
+            let start = inner.slabs[i];
+            let in_slab = |&addr: &usize| start <= addr && addr < start + slab_size;
+            if inner.free.iter().filter(|addr| in_slab(addr)).count() < Self::objects_per_slab() {
+                i += 1;
+                continue;
+            }
+            inner.free.retain(|addr| !in_slab(addr));
+            inner.slabs.swap_remove(i);
+            let pa = start - PHYSICAL_MEMORY_OFFSET;
+            dealloc_frames(Frame::of_addr(PhysAddr::new(pa)), Self::slab_pages());
+        }
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        let inner = self.inner.lock();
+        CacheStats {
+            name: self.name,
+            object_size: Self::object_size(),
+            objects_per_slab: Self::objects_per_slab(),
+            slabs: inner.slabs.len(),
+            active: inner.allocs - inner.frees,
+            constructed: inner.constructed.len(),
+            allocs: inner.allocs,
+            frees: inner.frees,
+        }
+    }
+}
+
+// 从缓存中分配的对象，被 drop 时归还给缓存
+pub struct Cached<T: 'static> {
+    cache: &'static ObjectCache<T>,
+    ptr: NonNull<T>,
+}
+
+unsafe impl<T: Send> Send for Cached<T> {}
+
+impl<T> Cached<T> {
+    fn new(cache: &'static ObjectCache<T>, addr: usize) -> Cached<T> {
+        Cached {
+            cache,
+            ptr: NonNull::new(addr as *mut T).unwrap(),
+        }
+    }
+}
+
+impl<T> Deref for Cached<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { self.ptr.as_ref() }
+    }
+}
+
+impl<T> DerefMut for Cached<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { self.ptr.as_mut() }
+    }
+}
+
+impl<T> Drop for Cached<T> {
+    fn drop(&mut self) {
+        self.cache.free(self.ptr.as_ptr() as usize);
+    }
+}
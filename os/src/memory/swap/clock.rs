@@ -0,0 +1,56 @@
+use super::{PageReplace, SwapPage};
+use alloc::vec::Vec;
+use riscv::paging::PageTableFlags as EF;
+
+// 时钟算法：指针循环扫描所有页，跳过并清除 ACCESSED 位为 1 的页，换出第一个未被访问过的页
+pub struct ClockPageReplace {
+    pages: Vec<SwapPage>,
+    hand: usize,
+}
+
+impl ClockPageReplace {
+    pub fn new() -> ClockPageReplace {
+        ClockPageReplace {
+            pages: Vec::new(),
+            hand: 0,
+        }
+    }
+}
+
+impl PageReplace for ClockPageReplace {
+    // 新页插入到指针之前，即最后被扫描到
+    fn push(&mut self, page: SwapPage) {
+        self.pages.insert(self.hand, page);
+        self.hand += 1;
+    }
+
+    fn remove(&mut self, page: SwapPage) -> bool {
+        match self.pages.iter().position(|&p| p == page) {
+            Some(i) => {
+                self.pages.remove(i);
+                if i < self.hand {
+                    self.hand -= 1;
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    // 第一圈扫描清除了所有 ACCESSED 位，因此最多扫描两圈
+    fn pop(&mut self) -> Option<SwapPage> {
+        for _ in 0..=self.pages.len() * 2 {
+            if self.hand >= self.pages.len() {
+                self.hand = 0;
+            }
+            let page = *self.pages.get(self.hand)?;
+            if page.flags().contains(EF::ACCESSED) {
+                page.clear_flags(EF::ACCESSED);
+                self.hand += 1;
+            } else {
+                return Some(self.pages.remove(self.hand));
+            }
+        }
+        None
+    }
+}
@@ -0,0 +1,72 @@
+use super::{PageReplace, SwapPage};
+use alloc::vec::Vec;
+use riscv::paging::PageTableFlags as EF;
+
+// 改进的时钟算法：同时考虑 ACCESSED 和 DIRTY 位，优先换出未被访问且未被修改的页
+// 第一圈寻找 (A, D) = (0, 0) 的页，不修改标志
+// 第二圈寻找 (0, 1) 的页，同时清除经过的页的 ACCESSED 位，然后重复
+pub struct EnhancedClockPageReplace {
+    pages: Vec<SwapPage>,
+    hand: usize,
+}
+
+impl EnhancedClockPageReplace {
+    pub fn new() -> EnhancedClockPageReplace {
+        EnhancedClockPageReplace {
+            pages: Vec::new(),
+            hand: 0,
+        }
+    }
+
+    // 从指针处开始扫描一圈，寻找 ACCESSED 为 0 且 DIRTY 为 dirty 的页
+    fn scan(&mut self, dirty: bool, clear_accessed: bool) -> Option<SwapPage> {
+        for _ in 0..self.pages.len() {
+            if self.hand >= self.pages.len() {
+                self.hand = 0;
+            }
+            let page = self.pages[self.hand];
+            let flags = page.flags();
+            if !flags.contains(EF::ACCESSED) && flags.contains(EF::DIRTY) == dirty {
+                return Some(self.pages.remove(self.hand));
+            }
+            if clear_accessed {
+                page.clear_flags(EF::ACCESSED);
+            }
+            self.hand += 1;
+        }
+        None
+    }
+}
+
+impl PageReplace for EnhancedClockPageReplace {
+    fn push(&mut self, page: SwapPage) {
+        self.pages.insert(self.hand, page);
+        self.hand += 1;
+    }
+
+    fn remove(&mut self, page: SwapPage) -> bool {
+        match self.pages.iter().position(|&p| p == page) {
+            Some(i) => {
+                self.pages.remove(i);
+                if i < self.hand {
+                    self.hand -= 1;
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    // 两轮之后所有页的 ACCESSED 位都已被清除，第二轮一定能找到要换出的页
+    fn pop(&mut self) -> Option<SwapPage> {
+        for _ in 0..2 {
+            if let Some(page) = self.scan(false, false) {
+                return Some(page);
+            }
+            if let Some(page) = self.scan(true, true) {
+                return Some(page);
+            }
+        }
+        None
+    }
+}
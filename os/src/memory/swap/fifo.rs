@@ -0,0 +1,35 @@
+use super::{PageReplace, SwapPage};
+use alloc::collections::VecDeque;
+
+// 先进先出：换出最早被映射的页
+pub struct FifoPageReplace {
+    queue: VecDeque<SwapPage>,
+}
+
+impl FifoPageReplace {
+    pub fn new() -> FifoPageReplace {
+        FifoPageReplace {
+            queue: VecDeque::new(),
+        }
+    }
+}
+
+impl PageReplace for FifoPageReplace {
+    fn push(&mut self, page: SwapPage) {
+        self.queue.push_back(page);
+    }
+
+    fn remove(&mut self, page: SwapPage) -> bool {
+        match self.queue.iter().position(|&p| p == page) {
+            Some(i) => {
+                self.queue.remove(i);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn pop(&mut self) -> Option<SwapPage> {
+        self.queue.pop_front()
+    }
+}
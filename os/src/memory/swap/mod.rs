@@ -0,0 +1,283 @@
+// 置换算法通过 cargo feature 选择，默认为 FIFO ；同时指定多个时 clock 优先
+#[cfg(feature = "swap-clock")]
+mod clock;
+#[cfg(all(feature = "swap-enhanced-clock", not(feature = "swap-clock")))]
+mod enhanced_clock;
+#[cfg(not(any(feature = "swap-clock", feature = "swap-enhanced-clock")))]
+mod fifo;
+
+use super::access_pa_via_va;
+use super::frame_allocator::{frame_ref_count, map_frame, release_frame, FrameTracker};
+use super::paging::{edit_by_token, ActivePageTable};
+use crate::consts::*;
+use crate::sync::IrqMutex;
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::slice;
+use lazy_static::*;
+use riscv::addr::{Frame, PhysAddr};
+use riscv::paging::PageTableFlags as EF;
+
+// 页表项中由软件使用的一位，标记该页已被换出，此时页号字段记录的是它在交换区中的槽位
+pub const SWAPPED: EF = EF::RESERVED2;
+
+// 一个可以被换出的用户页：所在页表的 satp 值与页的虚拟地址
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SwapPage {
+    pub token: usize,
+    pub va: usize,
+}
+
+// 只有 clock 类算法需要读写页表项中的 ACCESSED 和 DIRTY 位
+#[cfg(any(feature = "swap-clock", feature = "swap-enhanced-clock"))]
+impl SwapPage {
+    // 读取该页的页表项标志，页未被映射时返回空的标志
+    pub fn flags(&self) -> EF {
+        let va = self.va;
+        edit_by_token(self.token, |pt| pt.flags(va).unwrap_or_else(EF::empty))
+    }
+
+    // 清除该页页表项中的一些标志，用于重置 ACCESSED 和 DIRTY 位
+    pub fn clear_flags(&self, flags: EF) {
+        let va = self.va;
+        edit_by_token(self.token, |pt| {
+            if let Some(entry) = pt.entry(va) {
+                entry.flags_mut().remove(flags);
+            }
+        });
+    }
+}
+
+// 页面置换算法
+pub trait PageReplace: Send {
+    // 一个新映射的页加入置换队列
+    fn push(&mut self, page: SwapPage);
+    // 页被解除映射或换出后移出置换队列，返回该页是否在队列中
+    fn remove(&mut self, page: SwapPage) -> bool;
+    // 选出一个要被换出的页，并将其移出置换队列
+    fn pop(&mut self) -> Option<SwapPage>;
+}
+
+// 交换区设备，以页为单位读写
+pub trait SwapDevice: Send {
+    // 能够容纳的页数
+    fn capacity(&self) -> usize;
+    fn read(&mut self, slot: usize, buf: &mut [u8]);
+    fn write(&mut self, slot: usize, buf: &[u8]);
+}
+
+// 用一段内存模拟的交换区
+pub struct RamSwap {
+    space: &'static mut [u8],
+}
+
+impl RamSwap {
+    pub fn new(space: &'static mut [u8]) -> RamSwap {
+        RamSwap { space }
+    }
+}
+
+impl SwapDevice for RamSwap {
+    fn capacity(&self) -> usize {
+        self.space.len() / PAGE_SIZE
+    }
+
+    fn read(&mut self, slot: usize, buf: &mut [u8]) {
+        buf.copy_from_slice(&self.space[slot * PAGE_SIZE..(slot + 1) * PAGE_SIZE]);
+    }
+
+    fn write(&mut self, slot: usize, buf: &[u8]) {
+        self.space[slot * PAGE_SIZE..(slot + 1) * PAGE_SIZE].copy_from_slice(buf);
+    }
+}
+
+struct SwapManager {
+    device: Box<dyn SwapDevice>,
+    policy: Box<dyn PageReplace>,
+    free_slots: Vec<usize>, // 已回收、可再次使用的槽位
+    next_slot: usize,       // 从未被使用过的最小槽位
+    tracked: usize,         // 置换队列中的页数
+}
+
+impl SwapManager {
+    fn new(device: Box<dyn SwapDevice>, policy: Box<dyn PageReplace>) -> SwapManager {
+        SwapManager {
+            device,
+            policy,
+            free_slots: Vec::new(),
+            next_slot: 0,
+            tracked: 0,
+        }
+    }
+
+    fn alloc_slot(&mut self) -> Option<usize> {
+        if let Some(slot) = self.free_slots.pop() {
+            return Some(slot);
+        }
+        if self.next_slot < self.device.capacity() {
+            self.next_slot += 1;
+            return Some(self.next_slot - 1);
+        }
+        None
+    }
+
+    fn track(&mut self, page: SwapPage) {
+        self.policy.push(page);
+        self.tracked += 1;
+    }
+
+    fn untrack(&mut self, page: SwapPage) {
+        if self.policy.remove(page) {
+            self.tracked -= 1;
+        }
+    }
+
+    // 分配一个物理页帧，物理内存不足时先回收内核对象缓存，仍然不足时换出一页
+    fn alloc_frame(&mut self) -> Option<FrameTracker> {
+        if let Some(frame) = FrameTracker::new(false) {
+            return Some(frame);
+        }
+        crate::process::shrink_caches();
+        if let Some(frame) = FrameTracker::new(false) {
+            return Some(frame);
+        }
+        if self.swap_out() {
+            FrameTracker::new(false)
+        } else {
+            None
+        }
+    }
+
+    // 按照置换算法换出一页，返回是否成功
+    // 被多个地址空间共享的页帧不会被换出
+    fn swap_out(&mut self) -> bool {
+        for _ in 0..self.tracked {
+            let page = match self.policy.pop() {
+                Some(page) => page,
+                None => return false,
+            };
+            let slot = match self.alloc_slot() {
+                Some(slot) => slot,
+                None => {
+                    self.policy.push(page);
+                    return false;
+                }
+            };
+            let device = &mut self.device;
+            let evicted = edit_by_token(page.token, |pt| {
+                let (pa, flags) = pt.translate(page.va).expect("swap out an unmapped page!");
+                let frame = Frame::of_addr(PhysAddr::new(pa));
+                if frame_ref_count(frame) > 1 {
+                    return false;
+                }
+                device.write(slot, frame_data(pa));
+                pt.entry(page.va)
+                    .unwrap()
+                    .set(slot_frame(slot), (flags - EF::VALID) | SWAPPED);
+                release_frame(frame);
+                true
+            });
+            if evicted {
+                self.tracked -= 1;
+                return true;
+            }
+            self.free_slots.push(slot);
+            self.policy.push(page);
+        }
+        false
+    }
+}
+
+lazy_static! {
+    static ref SWAP_MANAGER: IrqMutex<SwapManager> = IrqMutex::new(SwapManager::new(
+        Box::new(RamSwap::new(unsafe { &mut SWAP_SPACE[..] })),
+        new_policy()
+    ));
+}
+
+static mut SWAP_SPACE: [u8; SWAP_SIZE] = [0; SWAP_SIZE];
+
+#[cfg(not(any(feature = "swap-clock", feature = "swap-enhanced-clock")))]
+fn new_policy() -> Box<dyn PageReplace> {
+    Box::new(fifo::FifoPageReplace::new())
+}
+
+#[cfg(feature = "swap-clock")]
+fn new_policy() -> Box<dyn PageReplace> {
+    Box::new(clock::ClockPageReplace::new())
+}
+
+#[cfg(all(feature = "swap-enhanced-clock", not(feature = "swap-clock")))]
+fn new_policy() -> Box<dyn PageReplace> {
+    Box::new(enhanced_clock::EnhancedClockPageReplace::new())
+}
+
+fn frame_data(pa: usize) -> &'static mut [u8] {
+    unsafe { slice::from_raw_parts_mut(access_pa_via_va(pa) as *mut u8, PAGE_SIZE) }
+}
+
+// 换出的页表项中用页号字段记录槽位
+fn slot_frame(slot: usize) -> Frame {
+    Frame::of_addr(PhysAddr::new(slot * PAGE_SIZE))
+}
+
+// 为用户页分配一个物理页帧，物理内存不足时先换出一页
+pub fn alloc_frame() -> Option<FrameTracker> {
+    SWAP_MANAGER.lock().alloc_frame()
+}
+
+// 刚被映射的用户页 va 可以被换出
+// 清除页表项的 ACCESSED 和 DIRTY 位，之后由硬件在访问时重新设置，供置换算法使用
+pub fn track(pt: &mut ActivePageTable, va: usize) {
+    if let Some(entry) = pt.entry(va) {
+        entry.flags_mut().remove(EF::ACCESSED | EF::DIRTY);
+    }
+    let token = pt.token();
+    SWAP_MANAGER.lock().track(SwapPage { token, va });
+}
+
+// 用户页 va 即将被解除映射，不再参与置换
+pub fn untrack(pt: &mut ActivePageTable, va: usize) {
+    let token = pt.token();
+    SWAP_MANAGER.lock().untrack(SwapPage { token, va });
+}
+
+pub fn is_swapped(pt: &mut ActivePageTable, va: usize) -> bool {
+    match pt.entry(va) {
+        Some(entry) => entry.flags().contains(SWAPPED),
+        None => false,
+    }
+}
+
+// 若 va 所在页已被换出，将其换入并返回 true ，物理内存耗尽时返回 false
+pub fn swap_in(pt: &mut ActivePageTable, va: usize) -> bool {
+    if !is_swapped(pt, va) {
+        return false;
+    }
+    let mut manager = SWAP_MANAGER.lock();
+    let frame = match manager.alloc_frame() {
+        Some(frame) => frame,
+        None => return false,
+    };
+    // 分配页帧时可能换出了其他页，之后再读取页表项
+    let entry = pt.entry(va).unwrap();
+    let slot = entry.ppn();
+    let flags = (entry.flags() - SWAPPED) | EF::VALID;
+    manager.device.read(slot, frame_data(frame.start_address()));
+    manager.free_slots.push(slot);
+    entry.set(map_frame(frame), flags);
+    drop(manager);
+    track(pt, va);
+    true
+}
+
+// 若 va 所在页已被换出，释放它占用的槽位并清空页表项，返回 true
+pub fn discard(pt: &mut ActivePageTable, va: usize) -> bool {
+    if !is_swapped(pt, va) {
+        return false;
+    }
+    let entry = pt.entry(va).unwrap();
+    SWAP_MANAGER.lock().free_slots.push(entry.ppn());
+    entry.set_unused();
+    true
+}
@@ -0,0 +1,175 @@
+use super::structs::Process;
+use crate::consts::*;
+use crate::memory::memory_set::handler::{ByFrame, Lazy};
+use crate::memory::memory_set::MapError;
+use alloc::vec::Vec;
+use core::cmp::{max, min};
+use core::mem::size_of;
+use core::ptr;
+use riscv::paging::PageTableFlags as EF;
+
+const ELF_MAGIC: [u8; 4] = [0x7f, b'E', b'L', b'F'];
+const ELF_CLASS_32: u8 = 1;
+const ELF_DATA_LSB: u8 = 1;
+const ELF_TYPE_EXEC: u16 = 2;
+const ELF_MACHINE_RISCV: u16 = 0xf3;
+
+const PT_LOAD: u32 = 1;
+const PF_X: u32 = 1;
+const PF_W: u32 = 2;
+const PF_R: u32 = 4;
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct ElfHeader {
+    ident: [u8; 16],
+    type_: u16,
+    machine: u16,
+    version: u32,
+    entry: u32,
+    phoff: u32,
+    shoff: u32,
+    flags: u32,
+    ehsize: u16,
+    phentsize: u16,
+    phnum: u16,
+    shentsize: u16,
+    shnum: u16,
+    shstrndx: u16,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct ProgramHeader {
+    type_: u32,
+    offset: u32,
+    vaddr: u32,
+    paddr: u32,
+    filesz: u32,
+    memsz: u32,
+    flags: u32,
+    align: u32,
+}
+
+#[derive(Debug)]
+pub enum ElfError {
+    // 文件头不是 ELF 格式
+    BadMagic,
+    // 不是小端序的 32 位 RISC-V 可执行文件
+    Unsupported,
+    // 文件头或程序头指向了文件之外
+    Truncated,
+    // 段的地址不在用户地址空间中，或与用户栈重叠
+    BadSegment,
+    // 两个段落在了同一页中
+    OverlappingSegments,
+    // 找不到要执行的程序
+    NotFound,
+    // 没有足够的物理内存装载程序
+    OutOfMemory,
+}
+
+impl From<MapError> for ElfError {
+    fn from(err: MapError) -> ElfError {
+        match err {
+            MapError::OutOfMemory => ElfError::OutOfMemory,
+            _ => ElfError::BadSegment,
+        }
+    }
+}
+
+// 从 data 的 offset 处读出一个 T ， data 可能没有对齐
+fn read<T: Copy>(data: &[u8], offset: usize) -> Result<T, ElfError> {
+    match offset.checked_add(size_of::<T>()) {
+        Some(end) if end <= data.len() => {}
+        _ => return Err(ElfError::Truncated),
+    }
+    Ok(unsafe { ptr::read_unaligned(data.as_ptr().add(offset) as *const T) })
+}
+
+fn page_flags(flags: u32) -> EF {
+    let mut ret = EF::VALID | EF::USER;
+    if flags & PF_R != 0 {
+        ret |= EF::READABLE;
+    }
+    if flags & PF_W != 0 {
+        ret |= EF::WRITABLE;
+    }
+    if flags & PF_X != 0 {
+        ret |= EF::EXECUTABLE;
+    }
+    ret
+}
+
+impl Process {
+    // 装载一个静态链接的 ELF32 可执行文件，返回新进程和程序入口
+    pub fn from_elf(data: &[u8]) -> Result<(Process, usize), ElfError> {
+        let header: ElfHeader = read(data, 0)?;
+        if header.ident[0..4] != ELF_MAGIC {
+            return Err(ElfError::BadMagic);
+        }
+        if header.ident[4] != ELF_CLASS_32
+            || header.ident[5] != ELF_DATA_LSB
+            || header.type_ != ELF_TYPE_EXEC
+            || header.machine != ELF_MACHINE_RISCV
+            || header.phentsize as usize != size_of::<ProgramHeader>()
+        {
+            return Err(ElfError::Unsupported);
+        }
+
+        let mut process = Process::new();
+        // 已装载段所占的页范围，用于检查段之间是否重叠
+        let mut loaded: Vec<(usize, usize)> = Vec::new();
+        for i in 0..header.phnum as usize {
+            // 文件头中的偏移不可信，相加时可能溢出
+            let offset = i
+                .checked_mul(size_of::<ProgramHeader>())
+                .and_then(|offset| offset.checked_add(header.phoff as usize))
+                .ok_or(ElfError::Truncated)?;
+            let ph: ProgramHeader = read(data, offset)?;
+            if ph.type_ != PT_LOAD || ph.memsz == 0 {
+                continue;
+            }
+            let start = ph.vaddr as usize;
+            let end = start
+                .checked_add(ph.memsz as usize)
+                .ok_or(ElfError::BadSegment)?;
+            if ph.filesz > ph.memsz || end > USER_STACK_OFFSET {
+                return Err(ElfError::BadSegment);
+            }
+            let file_end = (ph.offset as usize)
+                .checked_add(ph.filesz as usize)
+                .ok_or(ElfError::Truncated)?;
+            if file_end > data.len() {
+                return Err(ElfError::Truncated);
+            }
+            let pages = (start / PAGE_SIZE, (end - 1) / PAGE_SIZE + 1);
+            if loaded.iter().any(|&(s, e)| pages.0 < e && s < pages.1) {
+                return Err(ElfError::OverlappingSegments);
+            }
+            loaded.push(pages);
+            // 只有包含文件内容的页需要立即装载，之后只含 .bss 的页按需分配
+            let split = max(
+                start,
+                (start + ph.filesz as usize + PAGE_SIZE - 1) & !(PAGE_SIZE - 1),
+            );
+            let split = min(split, end);
+            // 只含 .bss 且起始地址按页对齐的段没有需要立即装载的部分
+            if split > start {
+                process.vm.push(
+                    start,
+                    split,
+                    page_flags(ph.flags),
+                    ByFrame::new(),
+                    Some(&data[ph.offset as usize..file_end]),
+                )?;
+            }
+            if split < end {
+                process
+                    .vm
+                    .push(split, end, page_flags(ph.flags), Lazy::new(), None)?;
+            }
+        }
+        Ok((process, header.entry as usize))
+    }
+}
@@ -1,15 +1,201 @@
+mod elf;
+mod processor;
+mod scheduler;
 mod structs;
+mod thread_pool;
+
+use crate::consts::*;
+use crate::context::TrapFrame;
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use processor::Processor;
+use scheduler::Scheduler;
+use spin::Mutex;
 use structs::Thread;
+use thread_pool::ThreadPool;
+
+pub use elf::ElfError;
+pub use structs::{shrink_caches, JoinHandle, Process};
+
+global_asm!(include_str!("user.asm"));
+
+pub type Tid = usize;
+pub type ExitCode = usize;
+
+#[derive(Debug)]
+pub enum ForkError {
+    // 物理内存不足，无法复制地址空间或内核栈
+    OutOfMemory,
+    // tid 已经用完
+    NoTid,
+}
+
+static CPU: Processor = Processor::new();
+
+// 调度策略通过 cargo feature 选择，默认为时间片轮转；同时指定多个时 stride 优先
+#[cfg(feature = "sched-stride")]
+fn new_scheduler() -> Box<dyn Scheduler> {
+    Box::new(scheduler::StrideScheduler::new(TIME_SLICE))
+}
+
+#[cfg(all(feature = "sched-mlfq", not(feature = "sched-stride")))]
+fn new_scheduler() -> Box<dyn Scheduler> {
+    Box::new(scheduler::MLFQScheduler::new(TIME_SLICE))
+}
+
+#[cfg(not(any(feature = "sched-stride", feature = "sched-mlfq")))]
+fn new_scheduler() -> Box<dyn Scheduler> {
+    Box::new(scheduler::RRScheduler::new(TIME_SLICE))
+}
 
 pub fn init() {
-    let mut loop_thread = Thread::new_idle();
-    let mut hello_thread = Thread::new_kernel(hello_thread, 666);
-    loop_thread.switch_to(&mut hello_thread);
+    let thread_pool = ThreadPool::new(MAX_THREAD_NUM, new_scheduler());
+    CPU.init(Thread::new_idle(), Box::new(thread_pool));
+    spawn(|| {
+        let counter = Arc::new(Mutex::new(0));
+        let handles: Vec<JoinHandle> = (0..5)
+            .map(|i| {
+                let counter = counter.clone();
+                let handle = spawn(move || hello_thread(i, counter));
+                set_priority(handle.tid(), i + 1);
+                handle
+            })
+            .collect();
+        for handle in handles {
+            let tid = handle.tid();
+            let code = handle.join().expect("join failed!");
+            println!("thread {} exited with code {}", tid, code);
+        }
+        println!("counter = {}", *counter.lock());
+        println!("heap: {}", crate::memory::heap::usage());
+        for stats in structs::cache_stats().iter() {
+            println!("{}", stats);
+        }
+        0
+    });
+    spawn_user_init();
+    println!("++++setup process!++++");
 }
 
+pub fn run() -> ! {
+    CPU.run()
+}
+
+pub fn tick() {
+    CPU.tick();
+}
+
+pub fn set_priority(tid: Tid, priority: usize) {
+    CPU.set_priority(tid, priority);
+}
+
+pub fn current_tid() -> Tid {
+    CPU.current_tid()
+}
+
+pub fn exit(code: ExitCode) -> ! {
+    CPU.exit(code)
+}
+
+pub fn join(tid: Tid) -> Option<ExitCode> {
+    CPU.join(tid)
+}
+
+pub fn detach(tid: Tid) {
+    CPU.detach(tid);
+}
+
+pub fn yield_now() {
+    CPU.yield_now();
+}
+
+pub fn sleep(ticks: usize) {
+    CPU.sleep(ticks);
+}
+
+pub fn fork(tf: &TrapFrame) -> Result<Tid, ForkError> {
+    CPU.fork(tf)
+}
+
+// 以内置程序 name 替换当前进程的映像
+pub fn exec(name: &str, tf: &mut TrapFrame) -> Result<(), ElfError> {
+    let data = find_program(name).ok_or(ElfError::NotFound)?;
+    let (process, entry) = Process::from_elf(data)?;
+    CPU.exec(process, entry, tf);
+    Ok(())
+}
+
+pub fn wait(pid: Option<Tid>) -> Option<(Tid, ExitCode)> {
+    CPU.wait(pid)
+}
+
+// 处理当前进程访问 addr 引发的页错误，返回是否已处理
+pub fn handle_page_fault(addr: usize, store: bool) -> bool {
+    CPU.handle_page_fault(addr, store)
+}
+
+// 创建一个以闭包为执行体的内核线程
+pub fn spawn<F>(f: F) -> JoinHandle
+where
+    F: FnOnce() -> ExitCode + Send + 'static,
+{
+    JoinHandle::new(CPU.add_thread(Thread::new_closure(f)))
+}
+
+// 创建一个运行在 U 态的用户进程
+pub fn spawn_user(process: Process, entry: usize) -> JoinHandle {
+    JoinHandle::new(CPU.add_thread(Thread::new_user(process, entry)))
+}
+
+// 装载一个 ELF 可执行文件并在新的进程中运行
+pub fn spawn_elf(data: &[u8]) -> Result<JoinHandle, ElfError> {
+    let (process, entry) = Process::from_elf(data)?;
+    Ok(spawn_user(process, entry))
+}
+
+// 内核中内置的用户程序，由 user.asm 生成
+fn find_program(name: &str) -> Option<&'static [u8]> {
+    extern "C" {
+        fn _user_init_start();
+        fn _user_init_end();
+        fn _user_hello_start();
+        fn _user_hello_end();
+    }
+    let (start, end) = match name {
+        "init" => (_user_init_start as usize, _user_init_end as usize),
+        "hello" => (_user_hello_start as usize, _user_hello_end as usize),
+        _ => return None,
+    };
+    Some(unsafe { core::slice::from_raw_parts(start as *const u8, end - start) })
+}
+
+// 运行内置的 init 程序，并等待它退出
+fn spawn_user_init() {
+    let data = find_program("init").unwrap();
+    let handle = spawn_elf(data).expect("failed to load user init");
+    spawn(move || {
+        let tid = handle.tid();
+        let code = handle.join().expect("join failed!");
+        println!("user init {} exited with code {}", tid, code);
+        0
+    });
+}
+
+// 内核线程的入口函数返回后跳转到这里，a0 中的返回值即为退出码
 #[no_mangle]
-pub extern "C" fn hello_thread(arg: usize) -> ! {
-    println!("hello thread");
-    println!("arg is {}", arg);
-    loop {}
+pub extern "C" fn kernel_thread_exit(code: usize) -> ! {
+    exit(code)
+}
+
+fn hello_thread(arg: usize, counter: Arc<Mutex<usize>>) -> ExitCode {
+    println!("hello thread {}, tid = {}", arg, current_tid());
+    for i in 1..=0x40_0000 {
+        if i % 0x10_0000 == 0 {
+            println!("thread {} is running", arg);
+            *counter.lock() += 1;
+        }
+    }
+    println!("thread {} exit", arg);
+    arg * 10
 }
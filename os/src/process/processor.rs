@@ -0,0 +1,245 @@
+use super::structs::{Process, Thread};
+use super::thread_pool::{ThreadPool, WaitStatus};
+use super::{ExitCode, ForkError, Tid};
+use crate::clock::TICK;
+use crate::context::TrapFrame;
+use crate::interrupt::{disable_and_store, enable_and_wfi, restore};
+use crate::memory::slab::Cached;
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+use core::cell::UnsafeCell;
+use spin::Mutex;
+
+pub struct ProcessorInner {
+    pool: Box<ThreadPool>,                  // 所有线程的状态与调度器
+    idle: Cached<Thread>,                   // 负责调度的 idle 线程，运行在启动栈上
+    current: Option<(Tid, Cached<Thread>)>, // 正在运行的线程
+}
+
+pub struct Processor {
+    inner: UnsafeCell<Option<ProcessorInner>>,
+}
+
+// 只有一个核，且访问 inner 时都关闭了中断
+unsafe impl Sync for Processor {}
+
+impl Processor {
+    pub const fn new() -> Processor {
+        Processor {
+            inner: UnsafeCell::new(None),
+        }
+    }
+
+    pub fn init(&self, idle: Cached<Thread>, pool: Box<ThreadPool>) {
+        unsafe {
+            *self.inner.get() = Some(ProcessorInner {
+                pool,
+                idle,
+                current: None,
+            });
+        }
+    }
+
+    // 调用者必须关闭中断，否则时钟中断中的 tick 可能同时修改 inner
+    #[allow(clippy::mut_from_ref)]
+    unsafe fn inner_mut(&self) -> &mut ProcessorInner {
+        (*self.inner.get())
+            .as_mut()
+            .expect("Processor is not initialized!")
+    }
+
+    // 只有内核会通过这里创建线程，tid 用完时直接 panic
+    pub fn add_thread(&self, thread: Cached<Thread>) -> Tid {
+        let flags = disable_and_store();
+        // SAFETY: 已关闭中断
+        let tid = unsafe { self.inner_mut() }
+            .pool
+            .add(thread)
+            .expect("alloc tid failed!");
+        restore(flags);
+        tid
+    }
+
+    pub fn set_priority(&self, tid: Tid, priority: usize) {
+        let flags = disable_and_store();
+        // SAFETY: 已关闭中断
+        unsafe { self.inner_mut() }.pool.set_priority(tid, priority);
+        restore(flags);
+    }
+
+    pub fn current_tid(&self) -> Tid {
+        let flags = disable_and_store();
+        // SAFETY: 已关闭中断
+        let inner = unsafe { self.inner_mut() };
+        let tid = inner.current.as_ref().expect("no thread is running!").0;
+        restore(flags);
+        tid
+    }
+
+    // 退出当前线程，切换回 idle 线程后由它释放内核栈
+    pub fn exit(&self, code: ExitCode) -> ! {
+        disable_and_store();
+        // SAFETY: 已关闭中断
+        let inner = unsafe { self.inner_mut() };
+        let tid = inner.current.as_ref().unwrap().0;
+        inner.pool.exit(tid, code);
+        inner.current.as_mut().unwrap().1.switch_to(&mut inner.idle);
+        unreachable!("exited thread {} is scheduled again", tid);
+    }
+
+    // 主动让出 CPU ，当前线程回到就绪队列
+    pub fn yield_now(&self) {
+        let flags = disable_and_store();
+        // SAFETY: 已关闭中断
+        let inner = unsafe { self.inner_mut() };
+        if let Some((_, thread)) = inner.current.as_mut() {
+            thread.switch_to(&mut inner.idle);
+        }
+        restore(flags);
+    }
+
+    // 让当前线程睡眠 ticks 个时钟中断
+    pub fn sleep(&self, ticks: usize) {
+        let flags = disable_and_store();
+        // SAFETY: 已关闭中断
+        let inner = unsafe { self.inner_mut() };
+        let tid = inner.current.as_ref().unwrap().0;
+        // 用户态可能传入极大的 ticks ，饱和相加避免溢出
+        let deadline = unsafe { TICK }.saturating_add(ticks);
+        // 被唤醒时重新检查时间，防止被其他原因提前唤醒
+        while unsafe { TICK } < deadline {
+            inner.pool.sleep_until(tid, deadline);
+            inner.current.as_mut().unwrap().1.switch_to(&mut inner.idle);
+        }
+        restore(flags);
+    }
+
+    // 等待线程 tid 退出并回收它，返回其退出码
+    // tid 不存在、为当前线程或已被其他线程等待时返回 None
+    pub fn join(&self, tid: Tid) -> Option<ExitCode> {
+        let flags = disable_and_store();
+        // SAFETY: 已关闭中断
+        let inner = unsafe { self.inner_mut() };
+        let current = inner.current.as_ref().unwrap().0;
+        let ret = loop {
+            if let Some(code) = inner.pool.reap(tid) {
+                break Some(code);
+            }
+            if !inner.pool.wait_for(current, tid) {
+                break None;
+            }
+            inner.current.as_mut().unwrap().1.switch_to(&mut inner.idle);
+        };
+        restore(flags);
+        ret
+    }
+
+    // 之后不会再有线程 join tid ，它退出后直接被回收
+    pub fn detach(&self, tid: Tid) {
+        let flags = disable_and_store();
+        // SAFETY: 已关闭中断
+        unsafe { self.inner_mut() }.pool.detach(tid);
+        restore(flags);
+    }
+
+    // 复制当前进程，返回子进程的 tid
+    pub fn fork(&self, tf: &TrapFrame) -> Result<Tid, ForkError> {
+        let flags = disable_and_store();
+        // SAFETY: 已关闭中断
+        let inner = unsafe { self.inner_mut() };
+        let (tid, thread) = inner.current.as_ref().unwrap();
+        let tid = *tid;
+        let child_tid = match thread.fork(tf) {
+            Some(child) => inner.pool.add_child(tid, child).ok_or(ForkError::NoTid),
+            None => Err(ForkError::OutOfMemory),
+        };
+        restore(flags);
+        child_tid
+    }
+
+    // 用 process 替换当前进程的地址空间，并让 tf 返回到新程序的入口
+    pub fn exec(&self, process: Process, entry: usize, tf: &mut TrapFrame) {
+        let flags = disable_and_store();
+        // SAFETY: 已关闭中断
+        let thread = &mut unsafe { self.inner_mut() }.current.as_mut().unwrap().1;
+        *tf = TrapFrame::new_user_thread(entry, process.ustack_top);
+        unsafe {
+            process.vm.activate();
+        }
+        let process = Arc::new(Mutex::new(process));
+        // 新的页表已经生效，此时才能释放原来的地址空间
+        thread.process = Some(process);
+        restore(flags);
+    }
+
+    // 在当前进程的地址空间中处理页错误，当前线程为内核线程时返回 false
+    pub fn handle_page_fault(&self, addr: usize, store: bool) -> bool {
+        let flags = disable_and_store();
+        // SAFETY: 已关闭中断
+        let ret = match unsafe { self.inner_mut() }.current.as_ref() {
+            Some((_, thread)) => match thread.process.as_ref() {
+                Some(process) => process.lock().vm.handle_page_fault(addr, store),
+                None => false,
+            },
+            None => false,
+        };
+        restore(flags);
+        ret
+    }
+
+    // 等待子进程退出并回收它，返回其 tid 和退出码
+    // pid 为 None 时等待任意子进程，没有符合条件的子进程时返回 None
+    pub fn wait(&self, pid: Option<Tid>) -> Option<(Tid, ExitCode)> {
+        let flags = disable_and_store();
+        // SAFETY: 已关闭中断
+        let inner = unsafe { self.inner_mut() };
+        let current = inner.current.as_ref().unwrap().0;
+        let ret = loop {
+            match inner.pool.wait_child(current, pid) {
+                WaitStatus::Exited(tid, code) => break Some((tid, code)),
+                WaitStatus::NoChild => break None,
+                WaitStatus::Waiting => {
+                    inner.current.as_mut().unwrap().1.switch_to(&mut inner.idle);
+                }
+            }
+        };
+        restore(flags);
+        ret
+    }
+
+    // idle 线程的主循环：不断从线程池中取出线程运行，线程被换下后再交还给线程池
+    pub fn run(&self) -> ! {
+        disable_and_store();
+        // SAFETY: 已关闭中断
+        let inner = unsafe { self.inner_mut() };
+        loop {
+            if let Some(thread) = inner.pool.acquire() {
+                inner.current = Some(thread);
+                inner
+                    .idle
+                    .switch_to(&mut *inner.current.as_mut().unwrap().1);
+                let (tid, thread) = inner.current.take().unwrap();
+                inner.pool.retrieve(tid, thread);
+            } else {
+                // 没有就绪线程，打开中断等待
+                enable_and_wfi();
+                disable_and_store();
+            }
+        }
+    }
+
+    // 时钟中断时调用，唤醒睡眠时间已到的线程；当前线程时间片用完后切换回 idle 线程重新调度
+    pub fn tick(&self) {
+        // SAFETY: 在时钟中断中调用，此时中断是关闭的
+        let inner = match unsafe { &mut *self.inner.get() } {
+            Some(inner) => inner,
+            None => return,
+        };
+        inner.pool.wakeup_timers(unsafe { TICK });
+        if inner.current.is_some() && inner.pool.tick() {
+            let flags = disable_and_store();
+            inner.current.as_mut().unwrap().1.switch_to(&mut inner.idle);
+            restore(flags);
+        }
+    }
+}
@@ -0,0 +1,103 @@
+use super::{Scheduler, Tid};
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+
+const LEVEL_NUM: usize = 3; // 队列的层数，第 0 层优先级最高
+const BOOST_INTERVAL: usize = 100; // 每隔多少个时钟中断把所有线程提回第 0 层，防止饥饿
+
+// 多级反馈队列调度器：用完时间片的线程降一级，第 k 层的时间片为 base_time << k
+pub struct MLFQScheduler {
+    queues: Vec<VecDeque<Tid>>,
+    levels: Vec<usize>, // 以 Tid 为下标，记录线程所在的层
+    base_time: usize,
+    current: Option<Tid>, // 正在运行的线程
+    current_time: usize,
+    expired: bool, // 正在运行的线程是否用完了时间片
+    boost_time: usize,
+}
+
+impl MLFQScheduler {
+    pub fn new(base_time: usize) -> MLFQScheduler {
+        let mut queues = Vec::new();
+        queues.resize_with(LEVEL_NUM, VecDeque::new);
+        MLFQScheduler {
+            queues,
+            levels: Vec::new(),
+            base_time,
+            current: None,
+            current_time: 0,
+            expired: false,
+            boost_time: 0,
+        }
+    }
+
+    fn level(&mut self, tid: Tid) -> &mut usize {
+        if tid >= self.levels.len() {
+            self.levels.resize(tid + 1, 0);
+        }
+        &mut self.levels[tid]
+    }
+
+    fn boost(&mut self) {
+        for level in 1..LEVEL_NUM {
+            while let Some(tid) = self.queues[level].pop_front() {
+                self.queues[0].push_back(tid);
+            }
+        }
+        for level in self.levels.iter_mut() {
+            *level = 0;
+        }
+    }
+}
+
+impl Scheduler for MLFQScheduler {
+    fn push(&mut self, tid: Tid) {
+        let demote = self.current == Some(tid) && self.expired;
+        if self.current == Some(tid) {
+            self.current = None;
+        }
+        let level = self.level(tid);
+        if demote && *level + 1 < LEVEL_NUM {
+            *level += 1;
+        }
+        let level = *level;
+        self.queues[level].push_back(tid);
+    }
+
+    fn pop(&mut self) -> Option<Tid> {
+        for level in 0..LEVEL_NUM {
+            if let Some(tid) = self.queues[level].pop_front() {
+                self.current = Some(tid);
+                self.current_time = self.base_time << level;
+                self.expired = false;
+                return Some(tid);
+            }
+        }
+        None
+    }
+
+    fn tick(&mut self) -> bool {
+        self.boost_time += 1;
+        if self.boost_time >= BOOST_INTERVAL {
+            self.boost_time = 0;
+            self.boost();
+        }
+        if self.current_time > 0 {
+            self.current_time -= 1;
+        }
+        if self.current_time == 0 {
+            self.expired = true;
+        }
+        self.expired
+    }
+
+    // 多级反馈队列根据线程的运行情况动态调整优先级，不使用静态优先级
+    fn set_priority(&mut self, _tid: Tid, _priority: usize) {}
+
+    fn exit(&mut self, tid: Tid) {
+        *self.level(tid) = 0;
+        if self.current == Some(tid) {
+            self.current = None;
+        }
+    }
+}
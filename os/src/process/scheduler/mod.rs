@@ -0,0 +1,30 @@
+// 只编译被 feature 选中的调度器，选择规则与 process::new_scheduler 一致
+#[cfg(all(feature = "sched-mlfq", not(feature = "sched-stride")))]
+mod mlfq;
+#[cfg(not(any(feature = "sched-stride", feature = "sched-mlfq")))]
+mod rr;
+#[cfg(feature = "sched-stride")]
+mod stride;
+
+#[cfg(all(feature = "sched-mlfq", not(feature = "sched-stride")))]
+pub use mlfq::MLFQScheduler;
+#[cfg(not(any(feature = "sched-stride", feature = "sched-mlfq")))]
+pub use rr::RRScheduler;
+#[cfg(feature = "sched-stride")]
+pub use stride::StrideScheduler;
+
+use super::Tid;
+
+// 调度策略，由 ThreadPool 在线程状态变化时调用
+pub trait Scheduler {
+    // 线程进入就绪队列
+    fn push(&mut self, tid: Tid);
+    // 选出下一个要运行的线程
+    fn pop(&mut self) -> Option<Tid>;
+    // 时钟中断时调用，返回当前线程是否需要被换下
+    fn tick(&mut self) -> bool;
+    // 设置线程的优先级，数值越大优先级越高
+    fn set_priority(&mut self, tid: Tid, priority: usize);
+    // 线程被回收时调用，清除调度器为它记录的信息，以便 tid 被复用
+    fn exit(&mut self, tid: Tid);
+}
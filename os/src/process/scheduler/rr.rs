@@ -0,0 +1,45 @@
+use super::{Scheduler, Tid};
+use alloc::collections::VecDeque;
+
+// 时间片轮转调度器
+pub struct RRScheduler {
+    queue: VecDeque<Tid>, // 就绪队列
+    max_time: usize,      // 每个线程一次最多连续运行的时钟中断数
+    current_time: usize,  // 当前运行线程剩余的时间片
+}
+
+impl RRScheduler {
+    pub fn new(max_time: usize) -> RRScheduler {
+        RRScheduler {
+            queue: VecDeque::new(),
+            max_time,
+            current_time: 0,
+        }
+    }
+}
+
+impl Scheduler for RRScheduler {
+    fn push(&mut self, tid: Tid) {
+        self.queue.push_back(tid);
+    }
+
+    fn pop(&mut self) -> Option<Tid> {
+        let ret = self.queue.pop_front();
+        if ret.is_some() {
+            self.current_time = self.max_time;
+        }
+        ret
+    }
+
+    fn tick(&mut self) -> bool {
+        if self.current_time > 0 {
+            self.current_time -= 1;
+        }
+        self.current_time == 0
+    }
+
+    // 时间片轮转不区分优先级
+    fn set_priority(&mut self, _tid: Tid, _priority: usize) {}
+
+    fn exit(&mut self, _tid: Tid) {}
+}
@@ -0,0 +1,97 @@
+use super::{Scheduler, Tid};
+use alloc::vec::Vec;
+
+const BIG_STRIDE: usize = 0x7fff_ffff;
+
+#[derive(Clone, Copy)]
+struct StrideInfo {
+    pass: usize,     // 已经走过的路程
+    priority: usize, // 优先级越高，每次前进的步长 BIG_STRIDE / priority 越小
+    valid: bool,     // 该 tid 是否已被调度器记录过
+}
+
+impl Default for StrideInfo {
+    fn default() -> Self {
+        StrideInfo {
+            pass: 0,
+            priority: 1,
+            valid: false,
+        }
+    }
+}
+
+// Stride 调度器：每次选出 pass 最小的就绪线程，线程获得的 CPU 时间与优先级成正比
+pub struct StrideScheduler {
+    infos: Vec<StrideInfo>, // 以 Tid 为下标
+    queue: Vec<Tid>,        // 就绪线程
+    max_time: usize,
+    current_time: usize,
+    min_pass: usize, // 最近一次被选中线程的 pass ，新线程从这里起步，避免长期霸占 CPU
+}
+
+impl StrideScheduler {
+    pub fn new(max_time: usize) -> StrideScheduler {
+        StrideScheduler {
+            infos: Vec::new(),
+            queue: Vec::new(),
+            max_time,
+            current_time: 0,
+            min_pass: 0,
+        }
+    }
+
+    fn info(&mut self, tid: Tid) -> &mut StrideInfo {
+        if tid >= self.infos.len() {
+            self.infos.resize(tid + 1, StrideInfo::default());
+        }
+        &mut self.infos[tid]
+    }
+}
+
+// pass 可能溢出回绕，只要任意两个 pass 之差不超过 BIG_STRIDE ，按有符号差值比较即可
+fn pass_less(a: usize, b: usize) -> bool {
+    (a.wrapping_sub(b) as isize) < 0
+}
+
+impl Scheduler for StrideScheduler {
+    fn push(&mut self, tid: Tid) {
+        let min_pass = self.min_pass;
+        let info = self.info(tid);
+        if !info.valid {
+            info.valid = true;
+            info.pass = min_pass;
+        }
+        self.queue.push(tid);
+    }
+
+    fn pop(&mut self) -> Option<Tid> {
+        let mut best: Option<usize> = None;
+        for (i, &tid) in self.queue.iter().enumerate() {
+            match best {
+                Some(j) if !pass_less(self.infos[tid].pass, self.infos[self.queue[j]].pass) => {}
+                _ => best = Some(i),
+            }
+        }
+        let tid = self.queue.swap_remove(best?);
+        let info = &mut self.infos[tid];
+        self.min_pass = info.pass;
+        info.pass = info.pass.wrapping_add(BIG_STRIDE / info.priority);
+        self.current_time = self.max_time;
+        Some(tid)
+    }
+
+    fn tick(&mut self) -> bool {
+        if self.current_time > 0 {
+            self.current_time -= 1;
+        }
+        self.current_time == 0
+    }
+
+    fn set_priority(&mut self, tid: Tid, priority: usize) {
+        self.info(tid).priority = if priority == 0 { 1 } else { priority };
+    }
+
+    fn exit(&mut self, tid: Tid) {
+        *self.info(tid) = StrideInfo::default();
+    }
+}
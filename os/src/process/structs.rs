@@ -1,33 +1,127 @@
 extern crate alloc;
-use crate::context::Context;
+use super::{ExitCode, Tid};
+use crate::consts::*;
+use crate::context::{Context, TrapFrame};
+use crate::memory::memory_set::handler::Lazy;
+use crate::memory::memory_set::MemorySet;
+use crate::memory::slab::{CacheStats, Cached, ObjectCache};
 use alloc::alloc::{alloc, dealloc, Layout};
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+use core::mem;
+use lazy_static::*;
+use riscv::paging::PageTableFlags as EF;
 use riscv::register::satp;
+use spin::Mutex;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Status {
+    Ready,            // 在就绪队列中等待调度
+    Running,          // 正在 CPU 上运行
+    Sleeping,         // 被阻塞，不参与调度
+    Exited(ExitCode), // 已退出，等待被 join 回收
+}
+
+// 线程对象从专用的缓存中分配
+// 内核栈被释放后保留已分配的栈空间，新线程可以直接复用
+lazy_static! {
+    static ref THREAD_CACHE: ObjectCache<Thread> = ObjectCache::new("thread");
+    static ref KSTACK_CACHE: ObjectCache<KernelStack> =
+        ObjectCache::with_ctor("kernel stack", KernelStack::new);
+}
+
+pub fn cache_stats() -> [CacheStats; 2] {
+    [THREAD_CACHE.stats(), KSTACK_CACHE.stats()]
+}
+
+// 物理内存不足时，回收缓存中空闲的内核栈与 slab
+pub fn shrink_caches() {
+    KSTACK_CACHE.shrink();
+    THREAD_CACHE.shrink();
+}
 
 pub struct Thread {
-    pub context: Context,    // 线程相关的上下文
-    pub kstack: KernelStack, // 线程对应的内核栈
+    pub context: Context,                     // 线程相关的上下文
+    pub kstack: Cached<KernelStack>,          // 线程对应的内核栈
+    pub process: Option<Arc<Mutex<Process>>>, // 用户线程所属的进程，内核线程为 None
 }
 
 impl Thread {
-    pub fn new_idle() -> Thread {
+    pub fn new_idle() -> Cached<Thread> {
+        unsafe {
+            THREAD_CACHE
+                .alloc(Thread {
+                    context: Context::null(),
+                    kstack: KSTACK_CACHE.get().expect("alloc kernel stack failed!"),
+                    process: None,
+                })
+                .expect("alloc idle thread failed!")
+        }
+    }
+
+    pub fn new_kernel(entry: extern "C" fn(usize) -> usize, arg: usize) -> Cached<Thread> {
+        unsafe {
+            let kstack_ = KSTACK_CACHE.get().expect("alloc kernel stack failed!");
+            THREAD_CACHE
+                .alloc(Thread {
+                    context: Context::new_kernel_thread(
+                        entry,
+                        arg,
+                        kstack_.top(),
+                        satp::read().bits(),
+                    ),
+                    kstack: kstack_,
+                    process: None,
+                })
+                .expect("alloc kernel thread failed!")
+        }
+    }
+
+    // 创建进程的第一个线程，它将通过 sret 从 entry 开始在 U 态运行
+    pub fn new_user(process: Process, entry: usize) -> Cached<Thread> {
         unsafe {
-            Thread {
-                context: Context::null(),
-                kstack: KernelStack::new(),
-            }
+            let kstack_ = KSTACK_CACHE.get().expect("alloc kernel stack failed!");
+            let context = Context::new_user_thread(
+                entry,
+                process.ustack_top,
+                kstack_.top(),
+                process.vm.token(),
+            );
+            THREAD_CACHE
+                .alloc(Thread {
+                    context,
+                    kstack: kstack_,
+                    process: Some(Arc::new(Mutex::new(process))),
+                })
+                .expect("alloc user thread failed!")
         }
     }
 
-    pub fn new_kernel(entry: extern "C" fn(usize) -> !, arg: usize) -> Thread {
+    // 复制当前用户线程及其进程，子线程从 tf 处返回用户态；物理内存不足时返回 None
+    pub fn fork(&self, tf: &TrapFrame) -> Option<Cached<Thread>> {
+        let process = self.process.as_ref().expect("fork a kernel thread!");
+        let process = process.lock().fork()?;
         unsafe {
-            let kstack_ = KernelStack::new();
-            Thread {
-                context: Context::new_kernel_thread(entry, arg, kstack_.top(), satp::read().bits()),
+            let kstack_ = KSTACK_CACHE.get()?;
+            THREAD_CACHE.alloc(Thread {
+                context: Context::new_fork(tf, kstack_.top(), process.vm.token()),
                 kstack: kstack_,
-            }
+                process: Some(Arc::new(Mutex::new(process))),
+            })
         }
     }
 
+    // 以闭包作为线程的执行体，闭包的返回值即为退出码
+    pub fn new_closure<F>(f: F) -> Cached<Thread>
+    where
+        F: FnOnce() -> ExitCode + Send + 'static,
+    {
+        let f: Box<dyn FnOnce() -> ExitCode + Send> = Box::new(f);
+        // Box<dyn FnOnce> 是胖指针，再套一层 Box 才能放进一个 usize 传给入口函数
+        let arg = Box::into_raw(Box::new(f)) as usize;
+        Thread::new_kernel(closure_entry, arg)
+    }
+
     pub fn switch_to(&mut self, target: &mut Thread) {
         unsafe {
             self.context.switch(&mut target.context);
@@ -35,14 +129,85 @@ impl Thread {
     }
 }
 
+// 用户进程：拥有独立的地址空间和用户栈
+pub struct Process {
+    pub vm: MemorySet,
+    pub ustack_top: usize,
+}
+
+impl Process {
+    // 创建一个只映射了内核与用户栈的地址空间，用户栈按需分配
+    pub fn new() -> Process {
+        let mut vm = MemorySet::new();
+        vm.push(
+            USER_STACK_OFFSET,
+            USER_STACK_OFFSET + USER_STACK_SIZE,
+            EF::VALID | EF::READABLE | EF::WRITABLE | EF::USER,
+            Lazy::new(),
+            None,
+        )
+        .expect("map user stack failed!");
+        Process {
+            vm,
+            ustack_top: USER_STACK_OFFSET + USER_STACK_SIZE,
+        }
+    }
+
+    // 复制一份地址空间，物理页帧在父子进程之间写时复制；物理内存不足时返回 None
+    pub fn fork(&mut self) -> Option<Process> {
+        Some(Process {
+            vm: self.vm.fork().ok()?,
+            ustack_top: self.ustack_top,
+        })
+    }
+}
+
+extern "C" fn closure_entry(arg: usize) -> usize {
+    let f = unsafe { Box::from_raw(arg as *mut Box<dyn FnOnce() -> ExitCode + Send>) };
+    f()
+}
+
+// 线程的句柄，用于等待线程结束并获取其退出码
+pub struct JoinHandle {
+    tid: Tid,
+}
+
+impl JoinHandle {
+    pub fn new(tid: Tid) -> JoinHandle {
+        JoinHandle { tid }
+    }
+
+    pub fn tid(&self) -> Tid {
+        self.tid
+    }
+
+    pub fn join(self) -> Option<ExitCode> {
+        let tid = self.tid;
+        // join 会回收线程，不需要再分离它
+        mem::forget(self);
+        super::join(tid)
+    }
+}
+
+impl Drop for JoinHandle {
+    // 句柄被丢弃而没有 join 时分离线程，使它退出后被直接回收
+    fn drop(&mut self) {
+        super::detach(self.tid);
+    }
+}
+
 pub struct KernelStack(usize);
 const STACK_SIZE: usize = 0x8000;
 
 impl KernelStack {
-    pub fn new() -> KernelStack {
+    // 内存不足时返回 None
+    pub fn new() -> Option<KernelStack> {
         let bottom =
             unsafe { alloc(Layout::from_size_align(STACK_SIZE, STACK_SIZE).unwrap()) } as usize;
-        KernelStack(bottom)
+        if bottom == 0 {
+            return None;
+        }
+        Some(KernelStack(bottom))
     }
 
     pub fn top(&self) -> usize {
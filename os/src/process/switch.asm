@@ -27,6 +27,7 @@
     Load sp, 0(a1)
     Load s11, 1*XLENB(sp)
     csrw satp, s11
+    sfence.vma
     Load ra, 0*XLENB(sp)
     Load s0, 2*XLENB(sp)
     Load s1, 3*XLENB(sp)
@@ -40,7 +41,6 @@
     Load s9, 11*XLENB(sp)
     Load s10, 12*XLENB(sp)
     Load s11, 13*XLENB(sp)
-    mv a0, s0
     addi sp, sp, (XLENB*14)
 
     Store zero, 0(a1)
@@ -0,0 +1,266 @@
+use super::scheduler::Scheduler;
+use super::structs::{Status, Thread};
+use super::{ExitCode, Tid};
+use crate::memory::slab::Cached;
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+
+// 可复用的 tid 分配器：优先复用已回收的 tid
+struct TidAllocator {
+    next: Tid,      // 从未被分配过的最小 tid
+    max: Tid,       // tid 的上限（不含）
+    free: Vec<Tid>, // 已回收、可再次分配的 tid
+}
+
+impl TidAllocator {
+    fn new(max: Tid) -> TidAllocator {
+        TidAllocator {
+            next: 0,
+            max,
+            free: Vec::new(),
+        }
+    }
+
+    fn alloc(&mut self) -> Option<Tid> {
+        if let Some(tid) = self.free.pop() {
+            return Some(tid);
+        }
+        if self.next < self.max {
+            self.next += 1;
+            return Some(self.next - 1);
+        }
+        None
+    }
+
+    fn dealloc(&mut self, tid: Tid) {
+        self.free.push(tid);
+    }
+}
+
+pub struct ThreadInfo {
+    pub status: Status,
+    pub thread: Option<Cached<Thread>>, // 线程正在运行时，其所有权被交给 Processor
+    pub waiter: Option<Tid>,            // 正在 join 该线程的线程
+    pub parent: Option<Tid>,            // fork 出该进程的父进程
+    pub children: Vec<Tid>,             // 尚未被回收的子进程
+    pub wait_child: bool,               // 是否正在 waitpid 中等待子进程退出
+    pub detached: bool,                 // 父进程已先退出，退出后直接回收
+}
+
+// waitpid 的结果
+pub enum WaitStatus {
+    Exited(Tid, ExitCode), // 回收了一个已退出的子进程
+    Waiting,               // 子进程都还在运行，当前线程已被挂起
+    NoChild,               // 没有符合条件的子进程
+}
+
+pub struct ThreadPool {
+    threads: Vec<Option<ThreadInfo>>, // 以 Tid 为下标
+    tids: TidAllocator,
+    scheduler: Box<dyn Scheduler>,
+    timers: Vec<(usize, Tid)>, // 正在睡眠的线程及其被唤醒的时刻
+}
+
+impl ThreadPool {
+    pub fn new(size: usize, scheduler: Box<dyn Scheduler>) -> ThreadPool {
+        let mut threads = Vec::new();
+        threads.resize_with(size, Default::default);
+        ThreadPool {
+            threads,
+            tids: TidAllocator::new(size),
+            scheduler,
+            timers: Vec::new(),
+        }
+    }
+
+    // 加入一个新线程，并放入就绪队列
+    // tid 用完时返回 None ，thread 随之被释放
+    pub fn add(&mut self, thread: Cached<Thread>) -> Option<Tid> {
+        let tid = self.tids.alloc()?;
+        self.threads[tid] = Some(ThreadInfo {
+            status: Status::Ready,
+            thread: Some(thread),
+            waiter: None,
+            parent: None,
+            children: Vec::new(),
+            wait_child: false,
+            detached: false,
+        });
+        self.scheduler.push(tid);
+        Some(tid)
+    }
+
+    // 加入 parent 的一个子进程
+    pub fn add_child(&mut self, parent: Tid, thread: Cached<Thread>) -> Option<Tid> {
+        let tid = self.add(thread)?;
+        self.threads[tid].as_mut().unwrap().parent = Some(parent);
+        self.threads[parent].as_mut().unwrap().children.push(tid);
+        Some(tid)
+    }
+
+    // 取出下一个要运行的线程
+    pub fn acquire(&mut self) -> Option<(Tid, Cached<Thread>)> {
+        let tid = self.scheduler.pop()?;
+        let info = self.threads[tid].as_mut().expect("thread not exist!");
+        info.status = Status::Running;
+        Some((tid, info.thread.take().expect("thread is already running!")))
+    }
+
+    // 线程被换下后交还给线程池
+    // 仍可运行的线程重新放入就绪队列；已退出的线程在这里释放内核栈与上下文
+    pub fn retrieve(&mut self, tid: Tid, thread: Cached<Thread>) {
+        let info = self.threads[tid].as_mut().expect("thread not exist!");
+        match info.status {
+            Status::Running => {
+                info.status = Status::Ready;
+                info.thread = Some(thread);
+                self.scheduler.push(tid);
+            }
+            Status::Sleeping => info.thread = Some(thread),
+            Status::Exited(_) => {
+                drop(thread);
+                if info.detached {
+                    self.reap(tid);
+                }
+            }
+            Status::Ready => panic!("retrieve a ready thread!"),
+        }
+    }
+
+    pub fn tick(&mut self) -> bool {
+        self.scheduler.tick()
+    }
+
+    pub fn set_priority(&mut self, tid: Tid, priority: usize) {
+        self.scheduler.set_priority(tid, priority);
+    }
+
+    // 将线程标记为退出，唤醒正在等待它的线程，并让它的子进程成为孤儿
+    pub fn exit(&mut self, tid: Tid, code: ExitCode) {
+        let info = self.threads[tid].as_mut().expect("thread not exist!");
+        info.status = Status::Exited(code);
+        let waiter = info.waiter.take();
+        let parent = info.parent;
+        let children: Vec<Tid> = info.children.drain(..).collect();
+        if let Some(waiter) = waiter {
+            self.wakeup(waiter);
+        }
+        if let Some(parent) = parent {
+            let parent_info = self.threads[parent].as_mut().unwrap();
+            if parent_info.wait_child {
+                parent_info.wait_child = false;
+                self.wakeup(parent);
+            }
+        }
+        for child in children {
+            let child_info = self.threads[child].as_mut().unwrap();
+            child_info.parent = None;
+            child_info.detached = true;
+            // 已经退出的子进程不会再被调度，在这里直接回收
+            if let Status::Exited(_) = child_info.status {
+                self.reap(child);
+            }
+        }
+    }
+
+    pub fn sleep(&mut self, tid: Tid) {
+        let info = self.threads[tid].as_mut().expect("thread not exist!");
+        info.status = Status::Sleeping;
+    }
+
+    pub fn wakeup(&mut self, tid: Tid) {
+        if let Some(info) = self.threads[tid].as_mut() {
+            if info.status == Status::Sleeping {
+                info.status = Status::Ready;
+                self.scheduler.push(tid);
+            }
+        }
+    }
+
+    // 让线程睡眠到时钟中断计数达到 deadline 时
+    // 每个线程最多只有一个定时器，之前的定时器被替换
+    pub fn sleep_until(&mut self, tid: Tid, deadline: usize) {
+        self.sleep(tid);
+        self.timers.retain(|&(_, t)| t != tid);
+        self.timers.push((deadline, tid));
+    }
+
+    // 唤醒所有睡眠时间已到的线程
+    pub fn wakeup_timers(&mut self, now: usize) {
+        let mut i = 0;
+        while i < self.timers.len() {
+            if self.timers[i].0 <= now {
+                let (_, tid) = self.timers.swap_remove(i);
+                self.wakeup(tid);
+            } else {
+                i += 1;
+            }
+        }
+    }
+
+    // 若目标线程已退出，回收它的 tid 并返回退出码
+    pub fn reap(&mut self, tid: Tid) -> Option<ExitCode> {
+        let code = match self.threads.get(tid)?.as_ref()?.status {
+            Status::Exited(code) => code,
+            _ => return None,
+        };
+        if let Some(parent) = self.threads[tid].as_ref().unwrap().parent {
+            let children = &mut self.threads[parent].as_mut().unwrap().children;
+            children.retain(|&child| child != tid);
+        }
+        self.threads[tid] = None;
+        self.tids.dealloc(tid);
+        self.scheduler.exit(tid);
+        Some(code)
+    }
+
+    // 分离线程 tid ，已经退出的线程在这里直接回收
+    pub fn detach(&mut self, tid: Tid) {
+        if let Some(Some(info)) = self.threads.get_mut(tid) {
+            info.detached = true;
+            if let Status::Exited(_) = info.status {
+                self.reap(tid);
+            }
+        }
+    }
+
+    // 回收 current 的一个已退出的子进程， pid 为 None 时可以是任意子进程
+    // 子进程都还在运行时将 current 挂起，直到有子进程退出
+    pub fn wait_child(&mut self, current: Tid, pid: Option<Tid>) -> WaitStatus {
+        let children: Vec<Tid> = self.threads[current]
+            .as_ref()
+            .unwrap()
+            .children
+            .iter()
+            .cloned()
+            .filter(|&child| pid.is_none() || pid == Some(child))
+            .collect();
+        if children.is_empty() {
+            return WaitStatus::NoChild;
+        }
+        for child in children {
+            if let Some(code) = self.reap(child) {
+                return WaitStatus::Exited(child, code);
+            }
+        }
+        self.threads[current].as_mut().unwrap().wait_child = true;
+        self.sleep(current);
+        WaitStatus::Waiting
+    }
+
+    // 让 current 挂起，直到 target 退出
+    // 目标线程不存在或已有其他线程在等待它时返回 false
+    pub fn wait_for(&mut self, current: Tid, target: Tid) -> bool {
+        if current == target {
+            return false;
+        }
+        match self.threads.get_mut(target) {
+            Some(Some(info)) if info.waiter.is_none() || info.waiter == Some(current) => {
+                info.waiter = Some(current);
+            }
+            _ => return false,
+        }
+        self.sleep(current);
+        true
+    }
+}
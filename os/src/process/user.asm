@@ -0,0 +1,117 @@
+# 内置的测试用户程序，每个都是一个手写的、只有一个 PT_LOAD 段的 ELF32 可执行文件
+# 整个文件被装载到 USER_BASE ，所有指令都与位置无关
+
+    .equ USER_BASE, 0x10000000
+
+    .equ SYS_WRITE, 64
+    .equ SYS_EXIT, 93
+    .equ SYS_SLEEP, 101
+    .equ SYS_YIELD, 124
+    .equ SYS_GETPID, 172
+    .equ SYS_FORK, 220
+    .equ SYS_EXEC, 221
+    .equ SYS_WAITPID, 260
+
+# ELF header 与紧随其后的 program header ，整个文件作为一个可读可执行的段
+.macro USER_ELF_HEADER start, entry, end
+    .byte   0x7f, 'E', 'L', 'F'
+    .byte   1, 1, 1, 0          # ELFCLASS32, ELFDATA2LSB, EV_CURRENT
+    .zero   8
+    .half   2                   # e_type = ET_EXEC
+    .half   0xf3                # e_machine = EM_RISCV
+    .word   1                   # e_version
+    .word   USER_BASE + (\entry - \start)
+    .word   52                  # e_phoff
+    .word   0                   # e_shoff
+    .word   0                   # e_flags
+    .half   52                  # e_ehsize
+    .half   32                  # e_phentsize
+    .half   1                   # e_phnum
+    .half   40                  # e_shentsize
+    .half   0                   # e_shnum
+    .half   0                   # e_shstrndx
+    .word   1                   # p_type = PT_LOAD
+    .word   0                   # p_offset
+    .word   USER_BASE           # p_vaddr
+    .word   USER_BASE           # p_paddr
+    .word   \end - \start       # p_filesz
+    .word   \end - \start       # p_memsz
+    .word   5                   # p_flags = PF_R | PF_X
+    .word   0x1000              # p_align
+.endm
+
+    .section .rodata
+    .align 2
+
+# init ： fork 出一个子进程执行 hello ，等待它退出后以它的退出码退出
+    .globl _user_init_start
+_user_init_start:
+    USER_ELF_HEADER _user_init_start, user_init_entry, _user_init_end
+user_init_entry:
+    li      a7, SYS_FORK
+    ecall
+    bnez    a0, user_init_parent
+    # 子进程： exec("hello") ，失败时以 -1 退出
+    la      a0, user_init_hello
+    li      a7, SYS_EXEC
+    ecall
+    li      a0, -1
+    li      a7, SYS_EXIT
+    ecall
+user_init_parent:
+    # waitpid(-1, &status)
+    addi    sp, sp, -16
+    li      a0, -1
+    mv      a1, sp
+    li      a7, SYS_WAITPID
+    ecall
+    # write(1, user_init_msg, len)
+    li      a0, 1
+    la      a1, user_init_msg
+    la      a2, user_init_msg_end
+    sub     a2, a2, a1
+    li      a7, SYS_WRITE
+    ecall
+    # exit(status)
+    lw      a0, 0(sp)
+    li      a7, SYS_EXIT
+    ecall
+user_init_hello:
+    .asciz  "hello"
+user_init_msg:
+    .ascii  "init: child exited\n"
+user_init_msg_end:
+    .align 2
+    .globl _user_init_end
+_user_init_end:
+
+# hello ：依次调用 write, sleep, yield, getpid ，最后以 getpid 的返回值作为退出码退出
+    .globl _user_hello_start
+_user_hello_start:
+    USER_ELF_HEADER _user_hello_start, user_hello_entry, _user_hello_end
+user_hello_entry:
+    # write(1, user_hello_msg, len)
+    li      a0, 1
+    la      a1, user_hello_msg
+    la      a2, user_hello_msg_end
+    sub     a2, a2, a1
+    li      a7, SYS_WRITE
+    ecall
+    # sleep(10)
+    li      a0, 10
+    li      a7, SYS_SLEEP
+    ecall
+    # yield()
+    li      a7, SYS_YIELD
+    ecall
+    # exit(getpid())
+    li      a7, SYS_GETPID
+    ecall
+    li      a7, SYS_EXIT
+    ecall
+user_hello_msg:
+    .ascii  "hello from user mode!\n"
+user_hello_msg_end:
+    .align 2
+    .globl _user_hello_end
+_user_hello_end:
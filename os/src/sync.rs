@@ -0,0 +1,54 @@
+// 获取时关闭中断的自旋锁
+// 中断处理与 idle 线程都在关闭中断时运行，它们用到的锁（堆、物理页帧分配器、对象缓存、交换区）
+// 若被一个开着中断的线程持有，该线程被时钟中断抢占后它们会一直自旋，因此持有这些锁时必须关闭中断
+use crate::interrupt::{disable_and_store, restore};
+use core::mem::ManuallyDrop;
+use core::ops::{Deref, DerefMut};
+use spin::{Mutex, MutexGuard};
+
+pub struct IrqMutex<T> {
+    inner: Mutex<T>,
+}
+
+pub struct IrqMutexGuard<'a, T> {
+    guard: ManuallyDrop<MutexGuard<'a, T>>,
+    flags: usize, // 获取锁之前的中断状态
+}
+
+impl<T> IrqMutex<T> {
+    pub const fn new(value: T) -> IrqMutex<T> {
+        IrqMutex {
+            inner: Mutex::new(value),
+        }
+    }
+
+    pub fn lock(&self) -> IrqMutexGuard<T> {
+        let flags = disable_and_store();
+        IrqMutexGuard {
+            guard: ManuallyDrop::new(self.inner.lock()),
+            flags,
+        }
+    }
+}
+
+impl<T> Deref for IrqMutexGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.guard
+    }
+}
+
+impl<T> DerefMut for IrqMutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.guard
+    }
+}
+
+impl<T> Drop for IrqMutexGuard<'_, T> {
+    // 先释放锁，再恢复中断
+    fn drop(&mut self) {
+        unsafe { ManuallyDrop::drop(&mut self.guard) };
+        restore(self.flags);
+    }
+}
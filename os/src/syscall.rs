@@ -0,0 +1,177 @@
+// 系统调用 ABI ：
+// - 用户程序执行 ecall 发起系统调用， a7 中为系统调用号， a0 ~ a5 中为参数
+// - 返回值写回 a0 ，非负数表示成功，负数 -errno 表示失败
+// - 系统调用号与 Linux 在 RISC-V 上的编号保持一致
+
+use crate::consts::*;
+use crate::context::TrapFrame;
+use crate::io;
+use crate::memory::paging::{ActivePageTable, COPY_ON_WRITE};
+use crate::process::{self, ElfError, ForkError};
+use riscv::paging::PageTableFlags as EF;
+
+pub const SYS_WRITE: usize = 64;
+pub const SYS_EXIT: usize = 93;
+pub const SYS_SLEEP: usize = 101;
+pub const SYS_YIELD: usize = 124;
+pub const SYS_GETPID: usize = 172;
+pub const SYS_FORK: usize = 220;
+pub const SYS_EXEC: usize = 221;
+pub const SYS_WAITPID: usize = 260;
+
+pub const ENOENT: isize = 2;
+pub const ENOEXEC: isize = 8;
+pub const EBADF: isize = 9;
+pub const ECHILD: isize = 10;
+pub const EAGAIN: isize = 11;
+pub const ENOMEM: isize = 12;
+pub const EFAULT: isize = 14;
+pub const EINVAL: isize = 22;
+pub const ENOSYS: isize = 38;
+
+// exec 的程序名的最大长度
+const MAX_NAME_LEN: usize = 256;
+
+const STDOUT: usize = 1;
+const STDERR: usize = 2;
+
+pub fn syscall(id: usize, args: [usize; 6], tf: &mut TrapFrame) -> isize {
+    let ret = match id {
+        SYS_WRITE => sys_write(args[0], args[1], args[2]),
+        SYS_EXIT => sys_exit(args[0]),
+        SYS_SLEEP => sys_sleep(args[0]),
+        SYS_YIELD => sys_yield(),
+        SYS_GETPID => sys_getpid(),
+        SYS_FORK => sys_fork(tf),
+        SYS_EXEC => sys_exec(args[0], tf),
+        SYS_WAITPID => sys_waitpid(args[0] as isize, args[1]),
+        _ => {
+            println!("unknown syscall id {}", id);
+            Err(ENOSYS)
+        }
+    };
+    match ret {
+        Ok(value) => value,
+        Err(errno) => -errno,
+    }
+}
+
+type SysResult = Result<isize, isize>;
+
+// write(fd, buf, len) ：目前只支持向标准输出和标准错误写入，返回写入的字节数
+fn sys_write(fd: usize, buf: usize, len: usize) -> SysResult {
+    if fd != STDOUT && fd != STDERR {
+        return Err(EBADF);
+    }
+    // 返回值为 isize ，写入的字节数必须能表示为非负数
+    if len > isize::max_value() as usize {
+        return Err(EINVAL);
+    }
+    let data = user_slice(buf, len)?;
+    for &ch in data {
+        io::putchar(ch as char);
+    }
+    Ok(len as isize)
+}
+
+// exit(code) ：结束当前线程，不会返回
+fn sys_exit(code: usize) -> SysResult {
+    process::exit(code)
+}
+
+// sleep(ticks) ：睡眠 ticks 个时钟中断
+fn sys_sleep(ticks: usize) -> SysResult {
+    process::sleep(ticks);
+    Ok(0)
+}
+
+// yield() ：主动让出 CPU
+fn sys_yield() -> SysResult {
+    process::yield_now();
+    Ok(0)
+}
+
+// getpid() ：返回当前线程的 id
+fn sys_getpid() -> SysResult {
+    Ok(process::current_tid() as isize)
+}
+
+// fork() ：复制当前进程，父进程返回子进程的 pid ，子进程返回 0
+fn sys_fork(tf: &TrapFrame) -> SysResult {
+    match process::fork(tf) {
+        Ok(tid) => Ok(tid as isize),
+        Err(ForkError::OutOfMemory) => Err(ENOMEM),
+        Err(ForkError::NoTid) => Err(EAGAIN),
+    }
+}
+
+// exec(name) ：以名为 name 的程序替换当前进程的映像，成功时不会回到原来的程序
+fn sys_exec(name: usize, tf: &mut TrapFrame) -> SysResult {
+    let name = user_str(name)?;
+    match process::exec(name, tf) {
+        Ok(()) => Ok(0),
+        Err(ElfError::NotFound) => Err(ENOENT),
+        Err(ElfError::OutOfMemory) => Err(ENOMEM),
+        Err(_) => Err(ENOEXEC),
+    }
+}
+
+// waitpid(pid, status) ：等待子进程 pid 退出， pid 为 -1 时等待任意子进程
+// status 不为 0 时将退出码写入其中，返回被回收的子进程的 pid
+fn sys_waitpid(pid: isize, status: usize) -> SysResult {
+    if status != 0 {
+        check_user_range(status, core::mem::size_of::<i32>(), EF::WRITABLE)?;
+    }
+    let pid = if pid == -1 { None } else { Some(pid as usize) };
+    let (tid, code) = process::wait(pid).ok_or(ECHILD)?;
+    if status != 0 {
+        unsafe {
+            *(status as *mut i32) = code as i32;
+        }
+    }
+    Ok(tid as isize)
+}
+
+// 检查 [ptr, ptr + len) 是否为当前进程中带有 flags 权限的用户内存
+fn check_user_range(ptr: usize, len: usize, flags: EF) -> Result<(), isize> {
+    let end = ptr.checked_add(len).ok_or(EFAULT)?;
+    if end > KERNEL_OFFSET {
+        return Err(EFAULT);
+    }
+    let mut page_table = unsafe { ActivePageTable::new() };
+    let mut page = ptr & !(PAGE_SIZE - 1);
+    while page < end {
+        // 按需分配的页在第一次访问前还没有被映射
+        if page_table.flags(page).is_none() {
+            process::handle_page_fault(page, false);
+        }
+        match page_table.flags(page) {
+            // 写时复制页在写入时会被复制，视为可写
+            Some(f)
+                if f.contains(COPY_ON_WRITE) && (f | EF::WRITABLE).contains(EF::USER | flags) => {}
+            Some(f) if f.contains(EF::USER | flags) => {}
+            _ => return Err(EFAULT),
+        }
+        page += PAGE_SIZE;
+    }
+    Ok(())
+}
+
+fn user_slice(ptr: usize, len: usize) -> Result<&'static [u8], isize> {
+    check_user_range(ptr, len, EF::READABLE)?;
+    Ok(unsafe { core::slice::from_raw_parts(ptr as *const u8, len) })
+}
+
+// 读取用户内存中以 0 结尾的字符串
+fn user_str(ptr: usize) -> Result<&'static str, isize> {
+    for len in 0..MAX_NAME_LEN {
+        if len == 0 || (ptr + len) % PAGE_SIZE == 0 {
+            check_user_range(ptr + len, 1, EF::READABLE)?;
+        }
+        if unsafe { *((ptr + len) as *const u8) } == 0 {
+            let bytes = unsafe { core::slice::from_raw_parts(ptr as *const u8, len) };
+            return core::str::from_utf8(bytes).map_err(|_| ENOENT);
+        }
+    }
+    Err(ENOENT)
+}